@@ -0,0 +1,27 @@
+use std::env;
+
+fn main() {
+    // `jemalloc-sys` requests forwarding `Jemalloc` to the system allocator
+    // when built with a sanitizer intercepting `malloc`:
+    if env::var_os("DEP_JEMALLOC_SANITIZER_FALLBACK").is_some() {
+        println!("cargo:rustc-cfg=sanitizer_fallback");
+    }
+    // the `jemalloc_{version}` and `jemalloc_{feature}` cfgs of the linked
+    // `jemalloc`, see `jemalloc_sys::build_info::CFGS`:
+    let cfgs = env::var("DEP_JEMALLOC_CFGS").unwrap_or_default();
+    for cfg in cfgs.split(',').filter(|c| !c.is_empty()) {
+        println!("cargo:rustc-cfg={}", cfg);
+    }
+    // `jemalloc` reads its options from `MALLOC_CONF` with the symbol prefix
+    // in upper case, e.g., `_RJEM_MALLOC_CONF`:
+    let prefix = env::var("DEP_JEMALLOC_PREFIX").unwrap_or_default();
+    println!(
+        "cargo:rustc-env=JEMALLOCATOR_MALLOC_CONF_VAR={}MALLOC_CONF",
+        prefix.to_uppercase()
+    );
+    // the shared `jemalloc` of the `shared_library` feature is not installed
+    // to a directory searched by the dynamic linker:
+    if let Some(dir) = env::var_os("DEP_JEMALLOC_LIBDIR") {
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}", dir.to_str().unwrap());
+    }
+}
@@ -71,6 +71,30 @@ then
     done
 fi
 
+if [ "${TARGET}" = "x86_64-unknown-linux-gnu" ] && [ "${JEMALLOC_SYS_GIT_DEV_BRANCH}" != "1" ]
+then
+    # Check that jemalloc is built bit-for-bit identically in different
+    # directories:
+    for dir in target/repro-a target/repro-b; do
+        env -u JEMALLOC_SYS_RUN_JEMALLOC_TESTS CARGO_TARGET_DIR="${dir}" \
+            cargo build -vv --target "${TARGET}" \
+            --manifest-path jemalloc-sys/Cargo.toml
+    done
+    cmp target/repro-a/"${TARGET}"/debug/build/jemalloc-sys-*/out/build/lib/libjemalloc_pic.a \
+        target/repro-b/"${TARGET}"/debug/build/jemalloc-sys-*/out/build/lib/libjemalloc_pic.a
+fi
+
+if [ -d "jemalloc-sys/configure/targets/${TARGET}" ] && [ "${JEMALLOC_SYS_GIT_DEV_BRANCH}" != "1" ]
+then
+    # Check that the pre-generated configuration builds with the `cc` backend,
+    # which cannot run jemalloc's tests:
+    env -u JEMALLOC_SYS_RUN_JEMALLOC_TESTS JEMALLOC_SYS_BUILD_BACKEND=cc \
+        ${CARGO_CMD} test -vv --target "${TARGET}" \
+        --manifest-path jemalloc-sys/Cargo.toml
+    env -u JEMALLOC_SYS_RUN_JEMALLOC_TESTS JEMALLOC_SYS_BUILD_BACKEND=cc \
+        ${CARGO_CMD} test -vv --target "${TARGET}"
+fi
+
 ${CARGO_CMD} test -vv --target "${TARGET}"
 
 if [ "${JEMALLOC_SYS_GIT_DEV_BRANCH}" = "1" ]; then
@@ -83,6 +107,12 @@ fi
 
 ${CARGO_CMD} test -vv --target "${TARGET}" --features debug
 ${CARGO_CMD} test -vv --target "${TARGET}" --features stats
+case "${TARGET}" in
+    *"linux"*)
+        ${CARGO_CMD} test -vv --target "${TARGET}" --features auto_config \
+                     --test auto_config
+        ;;
+esac
 if [ "${JEMALLOC_SYS_GIT_DEV_BRANCH}" = "1" ]; then
     # FIXME: profiling tests broken on dev-branch
     # https://github.com/jemalloc/jemalloc/issues/1477
@@ -126,12 +156,16 @@ case "${TARGET}" in
         ;;
 esac
 
-${CARGO_CMD} test -vv --target "${TARGET}" -p systest
+${CARGO_CMD} test -vv --target "${TARGET}" \
+             --manifest-path jemalloc-sys/Cargo.toml \
+             --features verify_bindings
 ${CARGO_CMD} test -vv --target "${TARGET}" \
              --manifest-path jemallocator-global/Cargo.toml
 ${CARGO_CMD} test -vv --target "${TARGET}" \
              --manifest-path jemallocator-global/Cargo.toml \
              --features force_global_jemalloc
+${CARGO_CMD} test -vv --target "${TARGET}" \
+             --manifest-path jemalloc-stats/Cargo.toml
 
 if [ "${TRAVIS_RUST_VERSION}" = "nightly"  ]
 then
@@ -0,0 +1,10 @@
+use std::env;
+
+fn main() {
+    // the `jemalloc_{version}` and `jemalloc_{feature}` cfgs of the linked
+    // `jemalloc`, see `jemalloc_sys::build_info::CFGS`:
+    let cfgs = env::var("DEP_JEMALLOC_CFGS").unwrap_or_default();
+    for cfg in cfgs.split(',').filter(|c| !c.is_empty()) {
+        println!("cargo:rustc-cfg={}", cfg);
+    }
+}
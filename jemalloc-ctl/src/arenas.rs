@@ -25,3 +25,126 @@ option! {
     /// ```
     mib_docs: /// See [`narenas`].
 }
+
+option! {
+    page[ str: b"arenas.page\0", non_str: 2 ] => libc::size_t |
+    ops: r |
+    docs:
+    /// Page size.
+    ///
+    /// Statistics like `stats.arenas.<i>.pdirty` are reported in pages, and
+    /// this is the size in bytes of each of those pages.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::arenas;
+    /// let page = arenas::page::mib().unwrap();
+    /// println!("page size: {}", page.read().unwrap());
+    /// # }
+    /// ```
+    mib_docs: /// See [`page`].
+}
+
+option! {
+    nbins[ str: b"arenas.nbins\0", non_str: 2 ] => libc::c_uint |
+    ops: r |
+    docs:
+    /// Number of bin size classes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::arenas;
+    /// let nbins = arenas::nbins::mib().unwrap();
+    /// println!("number of bin size classes: {}", nbins.read().unwrap());
+    /// # }
+    /// ```
+    mib_docs: /// See [`nbins`].
+}
+
+option! {
+    create[ str: b"arenas.create\0", non_str: 2 ] => libc::c_uint |
+    ops: r |
+    docs:
+    /// Creates a new arena and returns its index.
+    ///
+    /// The arena uses the default extent hooks. Threads allocate from it once
+    /// they are bound to it with [`::thread::arena`], and allocations can be
+    /// placed in it with `MALLOCX_ARENA`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::{arenas, thread};
+    /// let arena = arenas::create::read().unwrap();
+    /// thread::arena::write(arena).unwrap();
+    /// # }
+    /// ```
+    mib_docs: /// See [`create`].
+}
+
+/// Returns the index of the arena owning the allocation `ptr`, see
+/// `arenas.lookup`.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `jemalloc` and not been freed yet.
+/// Pointers into an allocation are not supported.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate jemallocator;
+/// # extern crate jemalloc_ctl;
+/// #
+/// # #[global_allocator]
+/// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+/// #
+/// # fn main() {
+/// use jemalloc_ctl::{arenas, thread};
+/// let arena = arenas::create::read().unwrap();
+/// thread::arena::write(arena).unwrap();
+/// // the cache still holds memory of the previous arena:
+/// thread::tcache_flush::invoke().unwrap();
+/// let data = Box::new(42_u64);
+/// let ptr = &*data as *const u64 as *const _;
+/// assert_eq!(unsafe { arenas::lookup(ptr) }.unwrap(), arena);
+/// # }
+/// ```
+pub unsafe fn lookup(
+    ptr: *const libc::c_void,
+) -> ::error::Result<libc::c_uint> {
+    let mut arena: libc::c_uint = 0;
+    let mut len = ::mem::size_of::<libc::c_uint>();
+    let mut ptr = ptr;
+    ::error::cvt(jemalloc_sys::mallctl(
+        b"arenas.lookup\0".as_ptr() as *const _,
+        &mut arena as *mut _ as *mut _,
+        &mut len,
+        &mut ptr as *mut _ as *mut _,
+        ::mem::size_of::<*const libc::c_void>(),
+    ))?;
+    Ok(arena)
+}
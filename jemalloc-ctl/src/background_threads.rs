@@ -0,0 +1,170 @@
+//! Control and activity of the background threads.
+//!
+//! With background threads enabled, `jemalloc` purges the unused dirty and
+//! muzzy pages asynchronously instead of on the allocation paths of the
+//! application threads. [`BackgroundThreads`] enables them with a maximum
+//! number of threads, waits until they are running, and reports their
+//! activity, e.g., to verify that the purging happens.
+//!
+//! Background threads are not supported on macOS.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate jemallocator;
+//! # extern crate jemalloc_ctl;
+//! #
+//! # #[global_allocator]
+//! # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//! #
+//! # fn main() {
+//! # #[cfg(not(target_os = "macos"))] {
+//! use std::cmp;
+//! use std::time::Duration;
+//! use jemalloc_ctl::background_threads::BackgroundThreads;
+//! use jemalloc_ctl::max_background_threads;
+//!
+//! // The maximum cannot exceed its default, i.e., the number of CPUs:
+//! let max = cmp::min(2, max_background_threads::read().unwrap());
+//! let threads = BackgroundThreads::new().unwrap();
+//! threads.enable(max).unwrap();
+//! assert!(threads.wait_running(Duration::from_secs(10)).unwrap());
+//!
+//! let activity = threads.activity().unwrap();
+//! assert!(activity.num_threads >= 1 && activity.num_threads <= max);
+//! println!("{} runs", activity.num_runs);
+//!
+//! threads.disable().unwrap();
+//! # } // #[cfg(..)]
+//! # }
+//! ```
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::Result;
+use stats::{
+    background_thread_num_runs_mib, background_thread_num_threads_mib,
+    background_thread_run_interval_mib,
+};
+use {background_thread_mib, epoch_mib, max_background_threads_mib};
+
+/// Activity of the background threads.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Activity {
+    /// Number of background threads running, see
+    /// [`::stats::background_thread_num_threads`].
+    pub num_threads: usize,
+    /// Total number of runs of the background threads, see
+    /// [`::stats::background_thread_num_runs`].
+    pub num_runs: u64,
+    /// Average interval between the runs, see
+    /// [`::stats::background_thread_run_interval`].
+    pub run_interval: Duration,
+}
+
+/// Controls the background threads through cached MIBs.
+#[derive(Copy, Clone)]
+pub struct BackgroundThreads {
+    enabled: background_thread_mib,
+    max: max_background_threads_mib,
+    epoch: epoch_mib,
+    num_threads: background_thread_num_threads_mib,
+    num_runs: background_thread_num_runs_mib,
+    run_interval: background_thread_run_interval_mib,
+}
+
+impl BackgroundThreads {
+    /// Returns a controller of the background threads.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            enabled: ::background_thread::mib()?,
+            max: ::max_background_threads::mib()?,
+            epoch: ::epoch::mib()?,
+            num_threads: ::stats::background_thread_num_threads::mib()?,
+            num_runs: ::stats::background_thread_num_runs::mib()?,
+            run_interval: ::stats::background_thread_run_interval::mib()?,
+        })
+    }
+
+    /// Enables the background threads, with up to `max` threads.
+    ///
+    /// `max` cannot exceed the default maximum, i.e., the number of CPUs.
+    ///
+    /// The threads are started asynchronously, see
+    /// [`BackgroundThreads::wait_running`].
+    pub fn enable(&self, max: usize) -> Result<()> {
+        self.max.write(max)?;
+        self.enabled.write(true)
+    }
+
+    /// Disables the background threads and waits for them to stop.
+    pub fn disable(&self) -> Result<()> {
+        self.enabled.write(false)
+    }
+
+    /// Returns `true` if the background threads are enabled.
+    pub fn is_enabled(&self) -> Result<bool> {
+        self.enabled.read()
+    }
+
+    /// Waits until at least one background thread is running, for up to
+    /// `timeout`.
+    ///
+    /// Returns `false` if no thread is running after `timeout`, e.g., if the
+    /// background threads are disabled.
+    pub fn wait_running(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.activity()?.num_threads > 0 {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Advances the epoch and reads the activity of the background threads.
+    pub fn activity(&self) -> Result<Activity> {
+        self.epoch.advance()?;
+        Ok(Activity {
+            num_threads: self.num_threads.read()?,
+            num_runs: self.num_runs.read()?,
+            run_interval: Duration::from_nanos(self.run_interval.read()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn enable_disable() {
+        // Enabling the threads changes global state that the other tests
+        // depend on:
+        let _lock = ::test_lock();
+        let threads = BackgroundThreads::new().unwrap();
+        let enabled = threads.is_enabled().unwrap();
+        let prev = ::max_background_threads::read().unwrap();
+        let max = ::std::cmp::min(2, prev);
+
+        threads.enable(max).unwrap();
+        assert!(threads.is_enabled().unwrap());
+        assert!(threads.wait_running(Duration::from_secs(10)).unwrap());
+        let a = threads.activity().unwrap();
+        assert!(a.num_threads >= 1 && a.num_threads <= max);
+
+        threads.disable().unwrap();
+        assert!(!threads.is_enabled().unwrap());
+        assert_eq!(threads.activity().unwrap().num_threads, 0);
+
+        ::max_background_threads::write(prev).unwrap();
+        if enabled {
+            threads.enable(prev).unwrap();
+        }
+    }
+}
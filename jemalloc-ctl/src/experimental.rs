@@ -0,0 +1,236 @@
+//! Experimental controls of `jemalloc`, see `experimental.*`.
+//!
+//! Requires `jemalloc` 5.2. These controls are not covered by the stability
+//! guarantees of `jemalloc` and may change between its releases.
+
+use error::{cvt, Result};
+use libc::c_void;
+use mem;
+
+/// Utilization of the slab or extent of an allocation, see
+/// `experimental.utilization.batch_query`.
+///
+/// Allocations of large size classes have their own extent, reported as
+/// full with a single region.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Utilization {
+    /// Number of free regions of the slab.
+    pub nfree: usize,
+    /// Number of regions of the slab.
+    pub nregs: usize,
+    /// Size of the slab or extent in bytes.
+    pub size: usize,
+}
+
+impl Utilization {
+    /// Returns `true` if all the regions of the slab are in use.
+    pub fn is_full(&self) -> bool {
+        self.nfree == 0
+    }
+
+    /// Fraction of the regions of the slab that are in use.
+    pub fn ratio(&self) -> f64 {
+        if self.nregs == 0 {
+            1.0
+        } else {
+            (self.nregs - self.nfree) as f64 / self.nregs as f64
+        }
+    }
+}
+
+/// Utilization of the slab of an allocation and of its bin, see
+/// `experimental.utilization.query`.
+#[cfg(jemalloc_5_3)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlabUtilization {
+    /// Address of the slab the bin allocates from next, or null for large
+    /// size classes.
+    pub slabcur: *const c_void,
+    /// Utilization of the slab of the allocation.
+    pub slab: Utilization,
+    /// Number of free regions of all the slabs of the bin.
+    ///
+    /// Zero unless `jemalloc` was built with statistics, see the `stats`
+    /// feature of `jemalloc-sys`.
+    pub bin_nfree: usize,
+    /// Number of regions of all the slabs of the bin.
+    ///
+    /// Zero unless `jemalloc` was built with statistics.
+    pub bin_nregs: usize,
+}
+
+#[cfg(jemalloc_5_3)]
+impl Default for SlabUtilization {
+    fn default() -> Self {
+        Self {
+            slabcur: ::ptr::null(),
+            slab: Utilization::default(),
+            bin_nfree: 0,
+            bin_nregs: 0,
+        }
+    }
+}
+
+#[cfg(jemalloc_5_3)]
+impl SlabUtilization {
+    /// Returns `true` if the allocation `ptr` the utilization was queried
+    /// for is in the slab the bin allocates from next.
+    pub fn is_slabcur(&self, ptr: *const c_void) -> bool {
+        let (start, ptr) = (self.slabcur as usize, ptr as usize);
+        !self.slabcur.is_null() && start <= ptr && ptr < start + self.slab.size
+    }
+
+    /// Fraction of the regions of all the slabs of the bin that are in use,
+    /// or `None` without statistics.
+    pub fn bin_ratio(&self) -> Option<f64> {
+        if self.bin_nregs == 0 {
+            None
+        } else {
+            Some(
+                (self.bin_nregs - self.bin_nfree) as f64
+                    / self.bin_nregs as f64,
+            )
+        }
+    }
+}
+
+/// Returns the utilization of the slab of the allocation `ptr` and of its
+/// bin, see `experimental.utilization.query`.
+///
+/// Requires `jemalloc` 5.3: the query of 5.2 crashes if the bin has no
+/// current slab, e.g., after its regions were freed. Use
+/// [`batch_utilization`] with 5.2 instead.
+///
+/// [`batch_utilization`]: fn.batch_utilization.html
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `jemalloc` and not been freed yet.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate jemallocator;
+/// # extern crate jemalloc_ctl;
+/// #
+/// # #[global_allocator]
+/// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+/// #
+/// # fn main() {
+/// use jemalloc_ctl::experimental;
+/// let data = Box::new([0_u8; 100]);
+/// let u = unsafe { experimental::utilization(data.as_ptr() as *const _) };
+/// let u = u.unwrap();
+/// assert!(u.slab.size >= 100);
+/// assert!(u.slab.nfree < u.slab.nregs);
+/// # }
+/// ```
+#[cfg(jemalloc_5_3)]
+pub unsafe fn utilization(ptr: *const c_void) -> Result<SlabUtilization> {
+    let mut out = SlabUtilization::default();
+    let mut len = mem::size_of::<SlabUtilization>();
+    let mut ptr = ptr;
+    cvt(jemalloc_sys::mallctl(
+        b"experimental.utilization.query\0".as_ptr() as *const _,
+        &mut out as *mut _ as *mut _,
+        &mut len,
+        &mut ptr as *mut _ as *mut _,
+        mem::size_of::<*const c_void>(),
+    ))?;
+    Ok(out)
+}
+
+/// Writes the utilization of the slabs of the allocations `ptrs` to `out`,
+/// see `experimental.utilization.batch_query`.
+///
+/// # Safety
+///
+/// The `ptrs` must have been returned by `jemalloc` and not been freed yet.
+///
+/// # Panics
+///
+/// If `ptrs` and `out` have different lengths.
+pub unsafe fn batch_utilization(
+    ptrs: &[*const c_void],
+    out: &mut [Utilization],
+) -> Result<()> {
+    assert_eq!(ptrs.len(), out.len(), "one result is required per pointer");
+    if ptrs.is_empty() {
+        return Ok(());
+    }
+    let mut len = mem::size_of_val(out);
+    cvt(jemalloc_sys::mallctl(
+        b"experimental.utilization.batch_query\0".as_ptr() as *const _,
+        out.as_mut_ptr() as *mut _,
+        &mut len,
+        ptrs.as_ptr() as *mut _,
+        mem::size_of_val(ptrs),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utilization_ratio() {
+        let u = Utilization {
+            nfree: 3,
+            nregs: 4,
+            size: 8,
+        };
+        assert!(!u.is_full());
+        assert_eq!(u.ratio(), 0.25);
+        assert_eq!(Utilization::default().ratio(), 1.0);
+    }
+
+    #[test]
+    #[cfg(jemalloc_5_3)]
+    fn slab_utilization() {
+        let u = SlabUtilization {
+            slabcur: 0x1000 as *const c_void,
+            slab: Utilization {
+                nfree: 1,
+                nregs: 4,
+                size: 0x100,
+            },
+            bin_nfree: 2,
+            bin_nregs: 8,
+        };
+        assert!(u.is_slabcur(0x1010 as *const c_void));
+        assert!(!u.is_slabcur(0x1100 as *const c_void));
+        assert_eq!(u.bin_ratio(), Some(0.75));
+        assert_eq!(SlabUtilization::default().bin_ratio(), None);
+        assert!(!SlabUtilization::default().is_slabcur(::ptr::null()));
+    }
+
+    #[test]
+    fn batch_query() {
+        let a = unsafe { jemalloc_sys::malloc(16) };
+        let b = unsafe { jemalloc_sys::malloc(1 << 20) };
+        let ptrs = [a as *const c_void, b as *const c_void];
+        let mut out = [Utilization::default(); 2];
+        unsafe { batch_utilization(&ptrs, &mut out) }.unwrap();
+        assert!(out[0].nregs > 1);
+        assert_eq!(out[0].size % 16, 0);
+        assert!(out[1].is_full());
+        assert_eq!(out[1].nregs, 1);
+        assert!(out[1].size >= 1 << 20);
+        #[cfg(jemalloc_5_3)]
+        {
+            let query = unsafe { utilization(ptrs[0]) }.unwrap();
+            assert_eq!(out[0].nregs, query.slab.nregs);
+            assert_eq!(out[0].size, query.slab.size);
+            let large = unsafe { utilization(ptrs[1]) }.unwrap();
+            assert!(large.slabcur.is_null());
+            assert_eq!(large.slab, out[1]);
+        }
+        unsafe {
+            jemalloc_sys::free(a);
+            jemalloc_sys::free(b);
+        }
+        assert!(unsafe { batch_utilization(&[], &mut []) }.is_ok());
+    }
+}
@@ -0,0 +1,241 @@
+//! Publishing of allocator statistics through the [`metrics`] facade.
+//!
+//! [`publish`] sets a gauge for each statistic of a [`Snapshot`], and a
+//! [`Publisher`] does so periodically on a background thread. The gauges use
+//! the same names as the [`openmetrics`] exporter, e.g.
+//! `jemalloc_allocated_bytes`, and are reported to whichever recorder is
+//! installed.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate jemallocator;
+//! extern crate jemalloc_ctl;
+//!
+//! use std::time::Duration;
+//! use jemalloc_ctl::exporter::metrics::Publisher;
+//!
+//! #[global_allocator]
+//! static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//!
+//! fn main() {
+//!     // Publish the statistics every 10 seconds until `publisher` is dropped:
+//!     let publisher = Publisher::spawn(Duration::from_secs(10)).unwrap();
+//!     # drop(publisher);
+//! }
+//! ```
+//!
+//! [`metrics`]: https://docs.rs/metrics
+//! [`Snapshot`]: ../../stats/struct.Snapshot.html
+//! [`openmetrics`]: ../openmetrics/index.html
+
+use std::io;
+use std::time::Duration;
+
+use periodic::Periodic;
+use stats::{Snapshot, SnapshotMib};
+
+/// Sets the `jemalloc_*` gauges to the statistics of `s`.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_precision_loss))]
+pub fn publish(s: &Snapshot) {
+    ::metrics::gauge!("jemalloc_epoch").set(s.epoch as f64);
+    ::metrics::gauge!("jemalloc_allocated_bytes").set(s.allocated as f64);
+    ::metrics::gauge!("jemalloc_active_bytes").set(s.active as f64);
+    ::metrics::gauge!("jemalloc_metadata_bytes").set(s.metadata as f64);
+    ::metrics::gauge!("jemalloc_resident_bytes").set(s.resident as f64);
+    ::metrics::gauge!("jemalloc_mapped_bytes").set(s.mapped as f64);
+    ::metrics::gauge!("jemalloc_retained_bytes").set(s.retained as f64);
+    ::metrics::gauge!("jemalloc_dirty_bytes").set(s.dirty_bytes() as f64);
+    ::metrics::gauge!("jemalloc_muzzy_bytes").set(s.muzzy_bytes() as f64);
+    ::metrics::gauge!("jemalloc_fragmentation_ratio")
+        .set(s.fragmentation_ratio());
+    ::metrics::gauge!("jemalloc_metadata_overhead_ratio")
+        .set(s.metadata_overhead());
+}
+
+/// Periodically publishes the allocator statistics on a background thread.
+///
+/// The thread is named `jemalloc-metrics`. It advances the epoch and calls
+/// [`publish`] once per interval, and it is stopped when the `Publisher` is
+/// dropped.
+pub struct Publisher {
+    _thread: Periodic,
+}
+
+impl Publisher {
+    /// Spawns a thread publishing the statistics every `interval`.
+    pub fn spawn(interval: Duration) -> io::Result<Self> {
+        let mib = Snapshot::mib()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Self::spawn_with(mib, interval)
+    }
+
+    /// Like [`Publisher::spawn`] but reusing the MIBs in `mib`.
+    pub fn spawn_with(
+        mib: SnapshotMib,
+        interval: Duration,
+    ) -> io::Result<Self> {
+        let thread =
+            Periodic::spawn("jemalloc-metrics", interval, move || {
+                // Failing to read the statistics leaves the gauges unchanged
+                // until the next interval:
+                if let Ok(s) = mib.take() {
+                    publish(&s);
+                }
+            })?;
+        Ok(Self { _thread: thread })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::{
+        Counter, Gauge, GaugeFn, Histogram, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+
+    /// A recorder keeping the last value of each gauge.
+    #[derive(Clone, Default)]
+    struct Gauges(Arc<Mutex<HashMap<String, f64>>>);
+
+    struct GaugeHandle(Gauges, String);
+
+    impl GaugeFn for GaugeHandle {
+        fn increment(&self, value: f64) {
+            *(self.0)
+                .0
+                .lock()
+                .unwrap()
+                .entry(self.1.clone())
+                .or_default() += value;
+        }
+
+        fn decrement(&self, value: f64) {
+            self.increment(-value)
+        }
+
+        fn set(&self, value: f64) {
+            (self.0).0.lock().unwrap().insert(self.1.clone(), value);
+        }
+    }
+
+    impl Gauges {
+        fn get(&self, name: &str) -> Option<f64> {
+            self.0.lock().unwrap().get(name).cloned()
+        }
+    }
+
+    impl Recorder for Gauges {
+        fn describe_counter(
+            &self,
+            _: KeyName,
+            _: Option<Unit>,
+            _: SharedString,
+        ) {
+        }
+
+        fn describe_gauge(
+            &self,
+            _: KeyName,
+            _: Option<Unit>,
+            _: SharedString,
+        ) {
+        }
+
+        fn describe_histogram(
+            &self,
+            _: KeyName,
+            _: Option<Unit>,
+            _: SharedString,
+        ) {
+        }
+
+        fn register_counter(&self, _: &Key, _: &Metadata) -> Counter {
+            Counter::noop()
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata) -> Gauge {
+            Gauge::from_arc(Arc::new(GaugeHandle(
+                self.clone(),
+                key.name().to_string(),
+            )))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::float_cmp))]
+    fn publish_sets_gauges() {
+        let gauges = Gauges::default();
+        let s = Snapshot::take().unwrap();
+        metrics::with_local_recorder(&gauges, || publish(&s));
+
+        assert_eq!(gauges.get("jemalloc_epoch"), Some(s.epoch as f64));
+        assert_eq!(
+            gauges.get("jemalloc_allocated_bytes"),
+            Some(s.allocated as f64)
+        );
+        assert_eq!(gauges.get("jemalloc_active_bytes"), Some(s.active as f64));
+        assert_eq!(
+            gauges.get("jemalloc_metadata_bytes"),
+            Some(s.metadata as f64)
+        );
+        assert_eq!(
+            gauges.get("jemalloc_resident_bytes"),
+            Some(s.resident as f64)
+        );
+        assert_eq!(gauges.get("jemalloc_mapped_bytes"), Some(s.mapped as f64));
+        assert_eq!(
+            gauges.get("jemalloc_retained_bytes"),
+            Some(s.retained as f64)
+        );
+        assert_eq!(
+            gauges.get("jemalloc_dirty_bytes"),
+            Some(s.dirty_bytes() as f64)
+        );
+        assert_eq!(
+            gauges.get("jemalloc_muzzy_bytes"),
+            Some(s.muzzy_bytes() as f64)
+        );
+        assert_eq!(
+            gauges.get("jemalloc_fragmentation_ratio"),
+            Some(s.fragmentation_ratio())
+        );
+        assert_eq!(
+            gauges.get("jemalloc_metadata_overhead_ratio"),
+            Some(s.metadata_overhead())
+        );
+        assert!(s.allocated > 0);
+    }
+
+    #[test]
+    fn publisher_publishes_until_dropped() {
+        // The publisher thread reports to the global recorder, which only
+        // this test installs:
+        let gauges = Gauges::default();
+        metrics::set_global_recorder(gauges.clone()).unwrap();
+
+        let publisher = Publisher::spawn(Duration::from_secs(3600)).unwrap();
+        // The statistics are published once right away:
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while gauges.get("jemalloc_epoch").is_none() {
+            assert!(Instant::now() < deadline, "no gauge was published");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(gauges.get("jemalloc_allocated_bytes").unwrap() > 0.0);
+        assert!(gauges.get("jemalloc_resident_bytes").unwrap() > 0.0);
+
+        // Dropping the publisher stops it without waiting for the interval:
+        let start = Instant::now();
+        drop(publisher);
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,8 @@
+//! Exporters of allocator statistics to monitoring systems.
+
+#[cfg(feature = "use_metrics")]
+pub mod metrics;
+#[cfg(feature = "openmetrics")]
+pub mod openmetrics;
+#[cfg(feature = "use_tracing")]
+pub mod tracing;
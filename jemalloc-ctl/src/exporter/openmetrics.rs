@@ -0,0 +1,651 @@
+//! Prometheus text exposition format exporter.
+//!
+//! The [`Exporter`] renders a [`Snapshot`] of the allocator statistics,
+//! including per-arena and per-bin statistics labeled with their arena and bin
+//! indices, in the [Prometheus text exposition format][format], which is also
+//! accepted by OpenMetrics scrapers. The [`Server`] serves these metrics at a
+//! local `/metrics` HTTP endpoint.
+//!
+//! Counters follow the `_total` naming convention and byte sizes use the
+//! `_bytes` suffix. All metrics are prefixed with `jemalloc_`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate jemallocator;
+//! extern crate jemalloc_ctl;
+//!
+//! use jemalloc_ctl::exporter::openmetrics::{Exporter, Options, Server};
+//!
+//! #[global_allocator]
+//! static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//!
+//! fn main() {
+//!     let exporter = Exporter::new(Options::default()).unwrap();
+//!     let mut buf = Vec::new();
+//!     exporter.render(&mut buf).unwrap();
+//!     println!("{}", String::from_utf8(buf).unwrap());
+//!
+//!     // Serve the metrics at http://127.0.0.1:9102/metrics:
+//!     let server = Server::bind("127.0.0.1:9102", exporter).unwrap();
+//!     server.run().unwrap();
+//! }
+//! ```
+//!
+//! [`Snapshot`]: ../../stats/struct.Snapshot.html
+//! [format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+use std::vec::Vec;
+
+use arenas;
+use error::Error;
+use stats::{ArenaSnapshot, BinSnapshot, Snapshot, SnapshotMib};
+
+/// Content type of the rendered metrics.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Exporter configuration.
+///
+/// All options default to `false`.
+#[derive(Copy, Clone, Default)]
+pub struct Options {
+    /// If set, per-arena statistics will be skipped.
+    ///
+    /// Bin statistics are reported per arena, so they are skipped as well.
+    pub skip_per_arena: bool,
+
+    /// If set, per-bin statistics will be skipped.
+    pub skip_bins: bool,
+
+    _p: (),
+}
+
+/// Renders allocator statistics in the Prometheus text exposition format.
+///
+/// The MIBs of all statistics are obtained once when the exporter is created.
+#[derive(Copy, Clone)]
+pub struct Exporter {
+    options: Options,
+    snapshot: SnapshotMib,
+    narenas: arenas::narenas_mib,
+    nbins: arenas::nbins_mib,
+}
+
+impl Exporter {
+    /// Creates a new exporter.
+    pub fn new(options: Options) -> io::Result<Self> {
+        Ok(Self {
+            options,
+            snapshot: Snapshot::mib().map_err(cvt)?,
+            narenas: arenas::narenas::mib().map_err(cvt)?,
+            nbins: arenas::nbins::mib().map_err(cvt)?,
+        })
+    }
+
+    /// Advances the epoch and writes the statistics to `writer`.
+    pub fn render<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let s = self.snapshot.take().map_err(cvt)?;
+
+        let mut arenas = Vec::new();
+        if !self.options.skip_per_arena {
+            for i in 0..self.narenas.read().map_err(cvt)? as usize {
+                // Uninitialized arenas have no statistics:
+                if let Ok(a) = self.snapshot.arena(i) {
+                    arenas.push(a);
+                }
+            }
+        }
+
+        let mut bins = Vec::new();
+        if !self.options.skip_bins {
+            let nbins = self.nbins.read().map_err(cvt)? as usize;
+            for a in &arenas {
+                for j in 0..nbins {
+                    bins.push(self.snapshot.bin(a.arena, j).map_err(cvt)?);
+                }
+            }
+        }
+
+        let w = &mut writer;
+        write_global(w, &s)?;
+        write_arenas(w, &arenas, s.page_size)?;
+        write_bins(w, &bins)?;
+        w.flush()
+    }
+}
+
+/// A minimal HTTP server exposing the metrics of an [`Exporter`] at
+/// `/metrics`.
+///
+/// Each connection serves a single request and is closed afterwards. Only
+/// `GET` requests are supported.
+///
+/// Connections are served one at a time, so reading the request and writing
+/// the response time out after [`Server::DEFAULT_TIMEOUT`], see
+/// [`Server::set_timeout`], so that idle clients do not block the scrapes.
+pub struct Server {
+    listener: TcpListener,
+    exporter: Exporter,
+    timeout: Duration,
+}
+
+impl Server {
+    /// Default read and write timeout of the connections.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Binds a server to `addr`.
+    ///
+    /// Binding to port `0` picks a free port, which can be queried with
+    /// [`Server::local_addr`].
+    pub fn bind<A>(addr: A, exporter: Exporter) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            exporter,
+            timeout: Self::DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets the read and write timeout of the connections.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timeout` is zero.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        assert!(timeout > Duration::from_secs(0), "zero timeout");
+        self.timeout = timeout;
+    }
+
+    /// Returns the address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves incoming connections until accepting a connection fails.
+    ///
+    /// Errors while serving an individual connection do not stop the server.
+    pub fn run(&self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let _ = self.handle(stream?);
+        }
+        Ok(())
+    }
+
+    /// Runs the server on a new thread named `jemalloc-metrics`.
+    pub fn spawn(self) -> io::Result<thread::JoinHandle<io::Result<()>>> {
+        thread::Builder::new()
+            .name("jemalloc-metrics".into())
+            .spawn(move || self.run())
+    }
+
+    /// Serves a single request on `stream`.
+    pub fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        // Only the request line is needed:
+        let mut request = [0_u8; 1024];
+        let len = stream.read(&mut request)?;
+        let line = request[..len]
+            .split(|&b| b == b'\r' || b == b'\n')
+            .next()
+            .unwrap_or(&[]);
+        let mut parts = line.split(|&b| b == b' ');
+        let method = parts.next().unwrap_or(&[]);
+        let path = parts
+            .next()
+            .and_then(|p| p.split(|&b| b == b'?').next())
+            .unwrap_or(&[]);
+
+        match (method, path) {
+            (b"GET", b"/metrics") => {
+                let mut body = Vec::new();
+                match self.exporter.render(&mut body) {
+                    Ok(()) => {
+                        respond(&mut stream, "200 OK", CONTENT_TYPE, &body)
+                    }
+                    Err(e) => respond(
+                        &mut stream,
+                        "500 Internal Server Error",
+                        "text/plain",
+                        e.to_string().as_bytes(),
+                    ),
+                }
+            }
+            (b"GET", _) => respond(
+                &mut stream,
+                "404 Not Found",
+                "text/plain",
+                b"Not Found\n",
+            ),
+            _ => respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                b"Method Not Allowed\n",
+            ),
+        }
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn write_global<W: Write>(w: &mut W, s: &Snapshot) -> io::Result<()> {
+    gauge(
+        w,
+        "epoch",
+        "Epoch at which the statistics were read.",
+        s.epoch,
+    )?;
+    gauge(
+        w,
+        "allocated_bytes",
+        "Bytes allocated by the application.",
+        s.allocated,
+    )?;
+    gauge(w, "active_bytes", "Bytes in active pages.", s.active)?;
+    gauge(
+        w,
+        "metadata_bytes",
+        "Bytes dedicated to metadata.",
+        s.metadata,
+    )?;
+    gauge(
+        w,
+        "resident_bytes",
+        "Bytes in physically resident data pages.",
+        s.resident,
+    )?;
+    gauge(w, "mapped_bytes", "Bytes in active extents.", s.mapped)?;
+    gauge(
+        w,
+        "retained_bytes",
+        "Bytes in retained virtual memory mappings.",
+        s.retained,
+    )?;
+    gauge(w, "dirty_bytes", "Bytes in dirty pages.", s.dirty_bytes())?;
+    gauge(w, "muzzy_bytes", "Bytes in muzzy pages.", s.muzzy_bytes())?;
+    gauge(
+        w,
+        "fragmentation_ratio",
+        "Ratio of active to allocated bytes.",
+        s.fragmentation_ratio(),
+    )?;
+    gauge(
+        w,
+        "metadata_overhead_ratio",
+        "Ratio of metadata to allocated bytes.",
+        s.metadata_overhead(),
+    )
+}
+
+fn write_arenas<W: Write>(
+    w: &mut W,
+    arenas: &[ArenaSnapshot],
+    page: usize,
+) -> io::Result<()> {
+    arena_family(
+        w,
+        "arena_threads",
+        GAUGE,
+        "Threads assigned to the arena.",
+        arenas,
+        |a| a.nthreads,
+    )?;
+    arena_family(
+        w,
+        "arena_active_bytes",
+        GAUGE,
+        "Bytes in active pages.",
+        arenas,
+        |a| a.active_pages * page,
+    )?;
+    arena_family(
+        w,
+        "arena_dirty_bytes",
+        GAUGE,
+        "Bytes in dirty pages.",
+        arenas,
+        |a| a.dirty_pages * page,
+    )?;
+    arena_family(
+        w,
+        "arena_muzzy_bytes",
+        GAUGE,
+        "Bytes in muzzy pages.",
+        arenas,
+        |a| a.muzzy_pages * page,
+    )?;
+    arena_family(
+        w,
+        "arena_mapped_bytes",
+        GAUGE,
+        "Mapped bytes.",
+        arenas,
+        |a| a.mapped,
+    )?;
+    arena_family(
+        w,
+        "arena_retained_bytes",
+        GAUGE,
+        "Retained bytes.",
+        arenas,
+        |a| a.retained,
+    )?;
+    arena_family(
+        w,
+        "arena_resident_bytes",
+        GAUGE,
+        "Resident bytes.",
+        arenas,
+        |a| a.resident,
+    )?;
+    arena_family(
+        w,
+        "arena_small_allocated_bytes",
+        GAUGE,
+        "Bytes allocated by small objects.",
+        arenas,
+        |a| a.small_allocated,
+    )?;
+    arena_family(
+        w,
+        "arena_large_allocated_bytes",
+        GAUGE,
+        "Bytes allocated by large objects.",
+        arenas,
+        |a| a.large_allocated,
+    )?;
+    arena_family(
+        w,
+        "arena_small_allocations_total",
+        COUNTER,
+        "Small allocation requests.",
+        arenas,
+        |a| a.small_nmalloc,
+    )?;
+    arena_family(
+        w,
+        "arena_small_deallocations_total",
+        COUNTER,
+        "Small deallocation requests.",
+        arenas,
+        |a| a.small_ndalloc,
+    )?;
+    arena_family(
+        w,
+        "arena_large_allocations_total",
+        COUNTER,
+        "Large allocation requests.",
+        arenas,
+        |a| a.large_nmalloc,
+    )?;
+    arena_family(
+        w,
+        "arena_large_deallocations_total",
+        COUNTER,
+        "Large deallocation requests.",
+        arenas,
+        |a| a.large_ndalloc,
+    )?;
+    arena_family(
+        w,
+        "arena_dirty_purged_pages_total",
+        COUNTER,
+        "Dirty pages purged.",
+        arenas,
+        |a| a.dirty_purged,
+    )?;
+    arena_family(
+        w,
+        "arena_muzzy_purged_pages_total",
+        COUNTER,
+        "Muzzy pages purged.",
+        arenas,
+        |a| a.muzzy_purged,
+    )
+}
+
+fn write_bins<W: Write>(w: &mut W, bins: &[BinSnapshot]) -> io::Result<()> {
+    bin_family(w, "bin_regions", GAUGE, "Regions in use.", bins, |b| {
+        b.curregs
+    })?;
+    bin_family(w, "bin_slabs", GAUGE, "Slabs in use.", bins, |b| b.curslabs)?;
+    bin_family(
+        w,
+        "bin_utilization_ratio",
+        GAUGE,
+        "Fraction of the regions of the current slabs that are in use.",
+        bins,
+        BinSnapshot::utilization,
+    )?;
+    bin_family(
+        w,
+        "bin_fragmented_bytes",
+        GAUGE,
+        "Unused bytes in the current slabs.",
+        bins,
+        BinSnapshot::fragmented_bytes,
+    )?;
+    bin_family(
+        w,
+        "bin_allocations_total",
+        COUNTER,
+        "Allocations served by the bin.",
+        bins,
+        |b| b.nmalloc,
+    )?;
+    bin_family(
+        w,
+        "bin_deallocations_total",
+        COUNTER,
+        "Allocations returned to the bin.",
+        bins,
+        |b| b.ndalloc,
+    )?;
+    bin_family(
+        w,
+        "bin_requests_total",
+        COUNTER,
+        "Allocation requests.",
+        bins,
+        |b| b.nrequests,
+    )
+}
+
+const GAUGE: &str = "gauge";
+const COUNTER: &str = "counter";
+
+fn family<W: Write>(
+    w: &mut W,
+    name: &str,
+    kind: &str,
+    help: &str,
+) -> io::Result<()> {
+    writeln!(w, "# HELP jemalloc_{} {}", name, help)?;
+    writeln!(w, "# TYPE jemalloc_{} {}", name, kind)
+}
+
+fn gauge<W, V>(w: &mut W, name: &str, help: &str, value: V) -> io::Result<()>
+where
+    W: Write,
+    V: Display,
+{
+    family(w, name, GAUGE, help)?;
+    writeln!(w, "jemalloc_{} {}", name, value)
+}
+
+fn arena_family<W, V, F>(
+    w: &mut W,
+    name: &str,
+    kind: &str,
+    help: &str,
+    arenas: &[ArenaSnapshot],
+    value: F,
+) -> io::Result<()>
+where
+    W: Write,
+    V: Display,
+    F: Fn(&ArenaSnapshot) -> V,
+{
+    if arenas.is_empty() {
+        return Ok(());
+    }
+    family(w, name, kind, help)?;
+    for a in arenas {
+        writeln!(
+            w,
+            "jemalloc_{}{{arena=\"{}\"}} {}",
+            name,
+            a.arena,
+            value(a)
+        )?;
+    }
+    Ok(())
+}
+
+fn bin_family<W, V, F>(
+    w: &mut W,
+    name: &str,
+    kind: &str,
+    help: &str,
+    bins: &[BinSnapshot],
+    value: F,
+) -> io::Result<()>
+where
+    W: Write,
+    V: Display,
+    F: Fn(&BinSnapshot) -> V,
+{
+    if bins.is_empty() {
+        return Ok(());
+    }
+    family(w, name, kind, help)?;
+    for b in bins {
+        writeln!(
+            w,
+            "jemalloc_{}{{arena=\"{}\",bin=\"{}\",size=\"{}\"}} {}",
+            name,
+            b.arena,
+            b.bin,
+            b.size,
+            value(b)
+        )?;
+    }
+    Ok(())
+}
+
+fn cvt(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn render() {
+        let mut buf = Vec::new();
+        Exporter::new(Options::default())
+            .unwrap()
+            .render(&mut buf)
+            .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains("# TYPE jemalloc_allocated_bytes gauge\n"));
+        assert!(text.contains("\njemalloc_resident_bytes "));
+        assert!(text.contains("jemalloc_arena_threads{arena=\"0\"} "));
+        assert!(
+            text.contains("# TYPE jemalloc_bin_allocations_total counter\n")
+        );
+        assert!(text
+            .contains("jemalloc_bin_regions{arena=\"0\",bin=\"0\",size=\""));
+    }
+
+    #[test]
+    fn render_skip_per_arena() {
+        let mut options = Options::default();
+        options.skip_per_arena = true;
+        let mut buf = Vec::new();
+        Exporter::new(options).unwrap().render(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains("jemalloc_allocated_bytes "));
+        assert!(!text.contains("jemalloc_arena_"));
+        assert!(!text.contains("jemalloc_bin_"));
+    }
+
+    #[test]
+    fn serve() {
+        let exporter = Exporter::new(Options::default()).unwrap();
+        let server = Server::bind("127.0.0.1:0", exporter).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn().unwrap();
+
+        let response =
+            get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.contains("\njemalloc_allocated_bytes "));
+
+        let response = get(addr, "GET /foo HTTP/1.1\r\n\r\n");
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            response
+        );
+
+        let response = get(addr, "POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+    }
+
+    #[test]
+    fn idle_client_times_out() {
+        let exporter = Exporter::new(Options::default()).unwrap();
+        let mut server = Server::bind("127.0.0.1:0", exporter).unwrap();
+        server.set_timeout(Duration::from_millis(100));
+        let addr = server.local_addr().unwrap();
+        server.spawn().unwrap();
+
+        // A client that never sends its request only blocks the server until
+        // the timeout:
+        let _idle = TcpStream::connect(addr).unwrap();
+        let response =
+            get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    }
+}
@@ -0,0 +1,391 @@
+//! [`tracing`] events for allocator statistics crossing thresholds.
+//!
+//! A [`Monitor`] compares consecutive [`Snapshot`]s against a set of
+//! [`Thresholds`] and emits an event each time one of them is crossed. A
+//! [`Watcher`] does so periodically on a background thread.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate jemallocator;
+//! extern crate jemalloc_ctl;
+//!
+//! use std::time::Duration;
+//! use jemalloc_ctl::exporter::tracing::{Thresholds, Watcher};
+//!
+//! #[global_allocator]
+//! static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//!
+//! fn main() {
+//!     let mut thresholds = Thresholds::default();
+//!     thresholds.resident_ratio = Some(1.5);
+//!     thresholds.retained_growth = Some(256 << 20);
+//!     let watcher =
+//!         Watcher::spawn(thresholds, Duration::from_secs(10)).unwrap();
+//!     # drop(watcher);
+//! }
+//! ```
+//!
+//! [`tracing`]: https://docs.rs/tracing
+//! [`Snapshot`]: ../../stats/struct.Snapshot.html
+
+use std::io;
+use std::time::Duration;
+use std::vec::Vec;
+
+use periodic::Periodic;
+use stats::{Snapshot, SnapshotMib};
+
+/// Thresholds checked by a [`Monitor`].
+///
+/// All thresholds default to `None`, which disables them.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Thresholds {
+    /// Ratio of resident to allocated bytes above which a warning is emitted,
+    /// see [`Snapshot::resident_ratio`].
+    ///
+    /// [`Snapshot::resident_ratio`]: ../../stats/struct.Snapshot.html#method.resident_ratio
+    pub resident_ratio: Option<f64>,
+
+    /// Growth of retained bytes between two consecutive snapshots above which
+    /// a warning is emitted.
+    pub retained_growth: Option<usize>,
+
+    _p: (),
+}
+
+/// A threshold crossed by a snapshot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Crossing {
+    /// The ratio of resident to allocated bytes rose above the threshold.
+    ResidentRatioExceeded {
+        /// Current ratio.
+        ratio: f64,
+        /// Threshold.
+        threshold: f64,
+    },
+    /// The ratio of resident to allocated bytes fell back to or below the
+    /// threshold.
+    ResidentRatioRecovered {
+        /// Current ratio.
+        ratio: f64,
+        /// Threshold.
+        threshold: f64,
+    },
+    /// Retained bytes grew by more than the threshold since the previous
+    /// snapshot.
+    RetainedGrowth {
+        /// Growth in bytes.
+        growth: usize,
+        /// Threshold in bytes.
+        threshold: usize,
+    },
+}
+
+/// Emits [`tracing`] events when snapshots cross [`Thresholds`].
+///
+/// The resident ratio threshold emits a `WARN` event when it is exceeded,
+/// and an `INFO` event when the ratio recovers; it does not emit events while
+/// the ratio stays on the same side of the threshold. The retained growth
+/// threshold emits a `WARN` event for every snapshot that exceeds it.
+///
+/// [`tracing`]: https://docs.rs/tracing
+pub struct Monitor {
+    thresholds: Thresholds,
+    previous: Option<Snapshot>,
+    resident_ratio_exceeded: bool,
+}
+
+impl Monitor {
+    /// Creates a monitor checking `thresholds`.
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            previous: None,
+            resident_ratio_exceeded: false,
+        }
+    }
+
+    /// Checks `s` against the thresholds and the previously observed
+    /// snapshot, emitting an event for each crossing.
+    ///
+    /// Returns the thresholds that were crossed.
+    pub fn observe(&mut self, s: &Snapshot) -> Vec<Crossing> {
+        let mut crossings = Vec::new();
+
+        if let Some(threshold) = self.thresholds.resident_ratio {
+            let ratio = s.resident_ratio();
+            let exceeded = ratio > threshold;
+            if exceeded != self.resident_ratio_exceeded {
+                self.resident_ratio_exceeded = exceeded;
+                crossings.push(if exceeded {
+                    ::tracing::warn!(
+                        ratio = ratio,
+                        threshold = threshold,
+                        resident = s.resident,
+                        allocated = s.allocated,
+                        "jemalloc resident to allocated ratio exceeded"
+                    );
+                    Crossing::ResidentRatioExceeded { ratio, threshold }
+                } else {
+                    ::tracing::info!(
+                        ratio = ratio,
+                        threshold = threshold,
+                        resident = s.resident,
+                        allocated = s.allocated,
+                        "jemalloc resident to allocated ratio recovered"
+                    );
+                    Crossing::ResidentRatioRecovered { ratio, threshold }
+                });
+            }
+        }
+
+        if let (Some(threshold), Some(previous)) =
+            (self.thresholds.retained_growth, self.previous)
+        {
+            let growth = s.retained.saturating_sub(previous.retained);
+            if growth > threshold {
+                ::tracing::warn!(
+                    growth = growth,
+                    threshold = threshold,
+                    retained = s.retained,
+                    "jemalloc retained memory growth exceeded"
+                );
+                crossings.push(Crossing::RetainedGrowth { growth, threshold });
+            }
+        }
+
+        self.previous = Some(*s);
+        crossings
+    }
+}
+
+/// Periodically checks the allocator statistics against [`Thresholds`] on a
+/// background thread.
+///
+/// The thread is named `jemalloc-tracing`. It advances the epoch and passes a
+/// new snapshot to a [`Monitor`] once per interval, and it is stopped when the
+/// `Watcher` is dropped.
+pub struct Watcher {
+    _thread: Periodic,
+}
+
+impl Watcher {
+    /// Spawns a thread checking `thresholds` every `interval`.
+    pub fn spawn(
+        thresholds: Thresholds,
+        interval: Duration,
+    ) -> io::Result<Self> {
+        let mib = Snapshot::mib()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Self::spawn_with(mib, thresholds, interval)
+    }
+
+    /// Like [`Watcher::spawn`] but reusing the MIBs in `mib`.
+    pub fn spawn_with(
+        mib: SnapshotMib,
+        thresholds: Thresholds,
+        interval: Duration,
+    ) -> io::Result<Self> {
+        let mut monitor = Monitor::new(thresholds);
+        let thread =
+            Periodic::spawn("jemalloc-tracing", interval, move || {
+                let s = match mib.take() {
+                    Ok(s) => s,
+                    Err(e) => {
+                        ::tracing::debug!(
+                            error = %e,
+                            "failed to read jemalloc statistics"
+                        );
+                        return;
+                    }
+                };
+                monitor.observe(&s);
+            })?;
+        Ok(Self { _thread: thread })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    /// An event recorded by [`Events`], with its fields formatted with
+    /// `Debug`.
+    #[derive(Debug, PartialEq)]
+    struct Recorded {
+        level: Level,
+        fields: HashMap<&'static str, String>,
+    }
+
+    impl Recorded {
+        fn message(&self) -> &str {
+            &self.fields["message"]
+        }
+    }
+
+    /// A subscriber recording the events.
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<Recorded>>>);
+
+    impl Events {
+        /// Runs `f` with this subscriber as the default and returns the
+        /// events emitted by `f`.
+        fn record<F: FnOnce()>(&self, f: F) -> Vec<Recorded> {
+            ::tracing::subscriber::with_default(self.clone(), f);
+            self.0.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+    impl<'a> Visit for Fields<'a> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for Events {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &Attributes) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut fields = HashMap::new();
+            event.record(&mut Fields(&mut fields));
+            self.0.lock().unwrap().push(Recorded {
+                level: *event.metadata().level(),
+                fields,
+            });
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn snapshot(
+        allocated: usize,
+        resident: usize,
+        retained: usize,
+    ) -> Snapshot {
+        let mut s = Snapshot::default();
+        s.allocated = allocated;
+        s.resident = resident;
+        s.retained = retained;
+        s
+    }
+
+    #[test]
+    fn resident_ratio() {
+        let mut thresholds = Thresholds::default();
+        thresholds.resident_ratio = Some(1.5);
+        let mut m = Monitor::new(thresholds);
+        let events = Events::default();
+
+        let e = events.record(|| {
+            assert!(m.observe(&snapshot(100, 120, 0)).is_empty());
+        });
+        assert!(e.is_empty());
+
+        let e = events.record(|| {
+            assert_eq!(
+                m.observe(&snapshot(100, 200, 0)),
+                [Crossing::ResidentRatioExceeded {
+                    ratio: 2.0,
+                    threshold: 1.5
+                }]
+            );
+        });
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].level, Level::WARN);
+        assert_eq!(
+            e[0].message(),
+            "jemalloc resident to allocated ratio exceeded"
+        );
+        assert_eq!(e[0].fields["ratio"], "2.0");
+        assert_eq!(e[0].fields["threshold"], "1.5");
+        assert_eq!(e[0].fields["resident"], "200");
+        assert_eq!(e[0].fields["allocated"], "100");
+
+        // Staying above the threshold does not emit another event:
+        let e = events.record(|| {
+            assert!(m.observe(&snapshot(100, 300, 0)).is_empty());
+        });
+        assert!(e.is_empty());
+
+        let e = events.record(|| {
+            assert_eq!(
+                m.observe(&snapshot(100, 100, 0)),
+                [Crossing::ResidentRatioRecovered {
+                    ratio: 1.0,
+                    threshold: 1.5
+                }]
+            );
+        });
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].level, Level::INFO);
+        assert_eq!(
+            e[0].message(),
+            "jemalloc resident to allocated ratio recovered"
+        );
+        assert_eq!(e[0].fields["ratio"], "1.0");
+    }
+
+    #[test]
+    fn retained_growth() {
+        let mut thresholds = Thresholds::default();
+        thresholds.retained_growth = Some(1000);
+        let mut m = Monitor::new(thresholds);
+        let events = Events::default();
+
+        let e = events.record(|| {
+            assert!(m.observe(&snapshot(0, 0, 5000)).is_empty());
+            assert!(m.observe(&snapshot(0, 0, 5500)).is_empty());
+        });
+        assert!(e.is_empty());
+
+        let e = events.record(|| {
+            assert_eq!(
+                m.observe(&snapshot(0, 0, 7000)),
+                [Crossing::RetainedGrowth {
+                    growth: 1500,
+                    threshold: 1000
+                }]
+            );
+        });
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].level, Level::WARN);
+        assert_eq!(e[0].message(), "jemalloc retained memory growth exceeded");
+        assert_eq!(e[0].fields["growth"], "1500");
+        assert_eq!(e[0].fields["threshold"], "1000");
+        assert_eq!(e[0].fields["retained"], "7000");
+
+        let e = events.record(|| {
+            assert!(m.observe(&snapshot(0, 0, 100)).is_empty());
+        });
+        assert!(e.is_empty());
+    }
+
+    #[test]
+    fn watcher_stops_on_drop() {
+        let watcher =
+            Watcher::spawn(Thresholds::default(), Duration::from_secs(3600))
+                .unwrap();
+        drop(watcher);
+    }
+}
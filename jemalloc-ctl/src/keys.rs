@@ -172,6 +172,12 @@ impl<T: MibArg> ops::IndexMut<usize> for Mib<T> {
     }
 }
 
+impl<T: MibArg> AsRef<[usize]> for MibStr<T> {
+    fn as_ref(&self) -> &[usize] {
+        self.0.as_ref()
+    }
+}
+
 impl<T: MibArg> ops::Index<usize> for MibStr<T> {
     type Output = usize;
     fn index(&self, idx: usize) -> &Self::Output {
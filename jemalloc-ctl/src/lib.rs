@@ -70,13 +70,21 @@
 //!     }
 //! }
 //! ```
+//!
+//! With the `use_std` feature enabled, `jemalloc_ctl::reporter::Reporter`
+//! does this on a background thread, keeping the recent samples and the
+//! allocation and purge rates between them.
 #![deny(missing_docs, intra_doc_link_resolution_failure)]
 #![cfg_attr(not(feature = "use_std"), no_std)]
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::module_name_repetitions))]
 
 extern crate jemalloc_sys;
 extern crate libc;
+#[cfg(feature = "use_metrics")]
+extern crate metrics;
 extern crate paste;
+#[cfg(feature = "use_tracing")]
+extern crate tracing;
 
 #[cfg(test)]
 extern crate jemallocator;
@@ -85,6 +93,42 @@ extern crate jemallocator;
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
+/// Serializes the tests that change the global state of `jemalloc`, e.g.,
+/// that toggle the background threads or create arenas.
+///
+/// This is a spin lock since `std` is not available without `use_std`.
+#[cfg(test)]
+static TEST_LOCK: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+/// Holds [`TEST_LOCK`] until it is dropped, also when a test panics.
+#[cfg(test)]
+struct TestGuard;
+
+#[cfg(test)]
+fn test_lock() -> TestGuard {
+    use std::sync::atomic::Ordering;
+    while TEST_LOCK
+        .compare_exchange_weak(
+            false,
+            true,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        std::hint::spin_loop();
+    }
+    TestGuard
+}
+
+#[cfg(test)]
+impl Drop for TestGuard {
+    fn drop(&mut self) {
+        TEST_LOCK.store(false, std::sync::atomic::Ordering::Release);
+    }
+}
+
 #[cfg(not(feature = "use_std"))]
 use core as std;
 use std::{fmt, mem, num, ops, ptr, result, slice, str};
@@ -93,11 +137,30 @@ use std::{fmt, mem, num, ops, ptr, result, slice, str};
 mod macros;
 
 pub mod arenas;
+#[cfg(feature = "use_std")]
+pub mod background_threads;
 pub mod config;
 mod error;
+#[cfg(jemalloc_5_2)]
+pub mod experimental;
+#[cfg(any(
+    feature = "openmetrics",
+    feature = "use_metrics",
+    feature = "use_tracing"
+))]
+pub mod exporter;
 mod keys;
 pub mod opt;
+#[cfg(feature = "use_std")]
+mod periodic;
+#[cfg(feature = "use_std")]
+pub mod pool;
+#[cfg(feature = "use_std")]
+pub mod pressure;
 pub mod raw;
+mod release;
+#[cfg(feature = "use_std")]
+pub mod reporter;
 pub mod stats;
 #[cfg(feature = "use_std")]
 pub mod stats_print;
@@ -105,6 +168,7 @@ pub mod thread;
 
 pub use error::{Error, Result};
 pub use keys::{Access, AsName, Mib, MibStr, Name};
+pub use release::{decay, decay_all, purge, purge_all, Released};
 
 option! {
     version[ str: b"version\0", str: 1 ] => &'static str |
@@ -70,6 +70,14 @@ macro_rules! r {
                     "background_thread" |
                     "max_background_threads"
                     if cfg!(target_os = "macos") => return,
+                    "lg_tcache_max" if cfg!(jemalloc_5_3) => {
+                        assert!($id::read().is_err());
+                        return;
+                    }
+                    "tcache_max" if !cfg!(jemalloc_5_3) => {
+                        assert!($id::read().is_err());
+                        return;
+                    }
                     _ => (),
                 }
 
@@ -121,19 +129,33 @@ macro_rules! w {
                     _ => (),
                 }
 
-                let _ = $id::write($ret_ty::default()).unwrap();
+                let _lock = ::test_lock();
+                let prev: $ret_ty = {
+                    use ::keys::Access;
+                    $id::name().read().unwrap()
+                };
+                // enabling the background threads after setting their
+                // maximum to `0` divides by zero:
+                let value = if stringify!($id) == "max_background_threads" {
+                    prev
+                } else {
+                    $ret_ty::default()
+                };
+
+                let _ = $id::write(value).unwrap();
 
                 let mib = $id::mib().unwrap();
-                let _ = mib.write($ret_ty::default()).unwrap();
+                let _ = mib.write(value).unwrap();
 
                 #[cfg(feature = "use_std")]
                 println!(
                     concat!(
                         stringify!($id),
                         " (write): \"{}\""),
-                    $ret_ty::default()
+                    value
                 );
 
+                $id::write(prev).unwrap();
             }
         }
     };
@@ -171,18 +193,67 @@ macro_rules! u {
                     _ => (),
                 }
 
-                let a = $id::update($ret_ty::default()).unwrap();
+                let _lock = ::test_lock();
+                let prev: $ret_ty = {
+                    use ::keys::Access;
+                    $id::name().read().unwrap()
+                };
+                // see the write test:
+                let value = if stringify!($id) == "max_background_threads" {
+                    prev
+                } else {
+                    $ret_ty::default()
+                };
+
+                let a = $id::update(value).unwrap();
 
                 let mib = $id::mib().unwrap();
-                let b = mib.update($ret_ty::default()).unwrap();
+                let b = mib.update(value).unwrap();
 
                 #[cfg(feature = "use_std")]
                 println!(
                     concat!(
                         stringify!($id),
                         " (update): (\"{}\", \"{}\") - \"{}\""),
-                    a, b, $ret_ty::default()
+                    a, b, value
                 );
+
+                $id::update(a).unwrap();
+            }
+        }
+    };
+}
+
+/// Invoke
+macro_rules! i {
+    ($id:ident => $ret_ty:ty) => {
+        paste::item! {
+            impl $id {
+                /// Performs the operation using string API.
+                pub fn invoke() -> ::error::Result<()> {
+                    // this is safe because the keys declared with this
+                    // operation do not invalidate memory in use:
+                    unsafe { ::raw::invoke(Self::NAME.as_bytes()) }
+                }
+            }
+
+            impl [<$id _mib>] {
+                /// Performs the operation using MIB API.
+                pub fn invoke(self) -> ::error::Result<()> {
+                    // this is safe because the keys declared with this
+                    // operation do not invalidate memory in use:
+                    unsafe { ::raw::invoke_mib(self.0.as_ref()) }
+                }
+            }
+
+            #[cfg(test)]
+            #[test]
+            #[cfg(not(target_arch = "mips64el"))]
+            fn [<$id _invoke_test>]() {
+                $id::invoke().unwrap();
+
+                let mib = $id::mib().unwrap();
+                mib.invoke().unwrap();
             }
         }
     };
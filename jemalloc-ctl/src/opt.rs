@@ -193,6 +193,8 @@ option! {
     /// At a minimum, all small size classes are cached, and at a maximum all
     /// large size classes are cached. The default maximum is 32 KiB (2^15).
     ///
+    /// Replaced by [`tcache_max`] in `jemalloc` 5.3, where reading it fails.
+    ///
     /// # Examples
     ///
     /// ```
@@ -204,13 +206,71 @@ option! {
     /// #
     /// # fn main() {
     /// use jemalloc_ctl::opt;
-    /// let lg_tcache_max = opt::lg_tcache_max::read().unwrap();
-    /// println!("max cached allocation size: {}", 1 << lg_tcache_max);
+    /// if let Ok(lg_tcache_max) = opt::lg_tcache_max::read() {
+    ///     println!("max cached allocation size: {}", 1 << lg_tcache_max);
+    /// }
     /// # }
     /// ```
     mib_docs: /// See [`lg_tcache_max`].
 }
 
+option! {
+    tcache_max[ str: b"opt.tcache_max\0", non_str: 2 ] => libc::size_t |
+    ops: r |
+    docs:
+    /// Maximum size class to cache in the thread-specific cache (`tcache`).
+    ///
+    /// Replaces [`lg_tcache_max`] in `jemalloc` 5.3; reading it fails with
+    /// earlier versions. The default maximum is 32 KiB.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::opt;
+    /// if let Ok(tcache_max) = opt::tcache_max::read() {
+    ///     println!("max cached allocation size: {}", tcache_max);
+    /// }
+    /// # }
+    /// ```
+    mib_docs: /// See [`tcache_max`].
+}
+
+#[cfg(jemalloc_5_2)]
+option! {
+    oversize_threshold[ str: b"opt.oversize_threshold\0", non_str: 2 ] => libc::size_t |
+    ops: r |
+    docs:
+    /// Size in bytes above which allocations are served by a dedicated arena.
+    ///
+    /// Requires `jemalloc` 5.2. The dedicated arena uses eager purging, which
+    /// keeps huge allocations from increasing the memory usage of the other
+    /// arenas. `0` disables the dedicated arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::opt;
+    /// let threshold = opt::oversize_threshold::read().unwrap();
+    /// println!("oversize threshold: {} bytes", threshold);
+    /// # }
+    /// ```
+    mib_docs: /// See [`oversize_threshold`].
+}
+
 option! {
     background_thread[ str: b"opt.background_thread\0", non_str: 2 ] => bool |
     ops: r |
@@ -0,0 +1,68 @@
+//! Background thread running a task at a fixed interval.
+
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Handle to a thread that runs a task periodically.
+///
+/// Dropping the handle stops the thread and waits for it to finish.
+pub(crate) struct Periodic {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Periodic {
+    /// Spawns a thread named `name` that calls `task` immediately and then
+    /// every `interval` until the handle is dropped.
+    pub(crate) fn spawn<F>(
+        name: &str,
+        interval: Duration,
+        mut task: F,
+    ) -> io::Result<Self>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let thread =
+            thread::Builder::new().name(name.into()).spawn(move || {
+                let (ref lock, ref cvar) = *thread_stop;
+                loop {
+                    task();
+                    let deadline = Instant::now() + interval;
+                    let mut stopped = lock.lock().unwrap();
+                    loop {
+                        if *stopped {
+                            return;
+                        }
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break;
+                        }
+                        stopped = cvar
+                            .wait_timeout(stopped, deadline - now)
+                            .unwrap()
+                            .0;
+                    }
+                }
+            })?;
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Periodic {
+    fn drop(&mut self) {
+        let (ref lock, ref cvar) = *self.stop;
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            // A panic of the task has already been reported by the thread:
+            let _ = thread.join();
+        }
+    }
+}
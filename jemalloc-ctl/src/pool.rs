@@ -0,0 +1,237 @@
+//! Arena binding and cache flushing for the workers of thread pools.
+//!
+//! The thread-specific caches (`tcache`) of `jemalloc` keep the memory freed
+//! by a thread for its next allocations, so parked workers of a thread pool
+//! hold on to memory that no other thread can use. [`Hooks`] provides
+//! callbacks for the start, park, and stop of the workers, which bind each
+//! worker to an arena according to a [`Policy`] and flush the cache of a
+//! worker when it parks or stops.
+//!
+//! The callbacks take the index of the worker and can be passed to the
+//! thread pool builders, e.g., to `start_handler` and `exit_handler` of
+//! `rayon::ThreadPoolBuilder`, or to `on_thread_start`, `on_thread_park`, and
+//! `on_thread_stop` of `tokio::runtime::Builder`, whose workers can take
+//! their index from [`Hooks::next_index`].
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate jemallocator;
+//! extern crate jemalloc_ctl;
+//!
+//! use std::sync::Arc;
+//! use std::thread;
+//! use jemalloc_ctl::pool::{Hooks, Policy};
+//!
+//! #[global_allocator]
+//! static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//!
+//! fn main() {
+//!     let hooks = Arc::new(Hooks::new(Policy::Dedicated));
+//!     let workers: Vec<_> = (0..4)
+//!         .map(|index| {
+//!             let hooks = hooks.clone();
+//!             thread::spawn(move || {
+//!                 let arena = hooks.start(index).unwrap();
+//!                 let work = vec![0_u8; 1024];
+//!                 drop(work);
+//!                 // the pool has no work for this worker:
+//!                 hooks.park().unwrap();
+//!                 hooks.stop(index).unwrap();
+//!                 arena
+//!             })
+//!         })
+//!         .collect();
+//!     for (index, worker) in workers.into_iter().enumerate() {
+//!         let arena = worker.join().unwrap();
+//!         assert_eq!(hooks.arena(index).unwrap(), arena);
+//!     }
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::vec::Vec;
+
+use error::Result;
+use libc::c_uint;
+use {arenas, opt, thread};
+
+/// How the workers of a thread pool are bound to arenas.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Worker `i` is bound to the automatic arena `i % opt.narenas`.
+    ///
+    /// This spreads the workers evenly over the arenas `jemalloc` creates for
+    /// the CPUs, instead of assigning them by thread creation order. Unlike
+    /// `arenas.narenas`, `opt.narenas` does not count the arenas created
+    /// manually, e.g., for the other policies.
+    RoundRobin,
+    /// Each worker is bound to an arena of its own.
+    ///
+    /// The arena of a worker is created on its first start and reused when a
+    /// worker with the same index is started again. This avoids contention
+    /// between the workers at the cost of more memory held by the arenas.
+    Dedicated,
+    /// All the workers are bound to a single arena of the pool.
+    ///
+    /// This keeps the allocations of the pool apart from those of the other
+    /// threads, e.g., to account for them in the statistics of the arena.
+    Shared,
+}
+
+/// Start, park, and stop callbacks for the workers of a thread pool.
+///
+/// The arenas created for the [`Policy::Dedicated`] and [`Policy::Shared`]
+/// policies are kept for the lifetime of the process, since allocations
+/// made by the workers may outlive them.
+pub struct Hooks {
+    policy: Policy,
+    /// The created arenas by worker index, or in the first slot for
+    /// [`Policy::Shared`].
+    arenas: Mutex<Vec<Option<c_uint>>>,
+    next: AtomicUsize,
+}
+
+impl Hooks {
+    /// Returns callbacks binding the workers to arenas according to `policy`.
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            arenas: Mutex::new(Vec::new()),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the policy of the callbacks.
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// Returns the arena of the worker `index`, creating it if required.
+    pub fn arena(&self, index: usize) -> Result<c_uint> {
+        let slot = match self.policy {
+            Policy::RoundRobin => {
+                let narenas = opt::narenas::read()? as usize;
+                return Ok((index % narenas) as c_uint);
+            }
+            Policy::Dedicated => index,
+            Policy::Shared => 0,
+        };
+        let mut created = self.arenas.lock().unwrap();
+        if created.len() <= slot {
+            created.resize(slot + 1, None);
+        }
+        match created[slot] {
+            Some(arena) => Ok(arena),
+            None => {
+                let arena = arenas::create::read()?;
+                created[slot] = Some(arena);
+                Ok(arena)
+            }
+        }
+    }
+
+    /// Returns a new worker index for thread pools whose callbacks do not
+    /// receive one.
+    ///
+    /// The indices start at `0` and are not reused.
+    pub fn next_index(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Start callback: binds the calling thread, the worker `index`, to its
+    /// arena and returns the arena.
+    ///
+    /// The cache of the thread is flushed, since it still holds memory of the
+    /// previous arena of the thread.
+    pub fn start(&self, index: usize) -> Result<c_uint> {
+        let arena = self.arena(index)?;
+        thread::arena::write(arena)?;
+        if thread::tcache_enabled::read()? {
+            thread::tcache_flush::invoke()?;
+        }
+        Ok(arena)
+    }
+
+    /// Park callback: releases the memory cached by the calling thread.
+    ///
+    /// With `jemalloc` 5.3 this uses `thread.idle`, which may also purge the
+    /// arena of the thread, and `thread.tcache.flush` otherwise, which fails
+    /// if the cache of the thread is disabled.
+    pub fn park(&self) -> Result<()> {
+        #[cfg(jemalloc_5_3)]
+        {
+            thread::idle::invoke()
+        }
+        #[cfg(not(jemalloc_5_3))]
+        {
+            thread::tcache_flush::invoke()
+        }
+    }
+
+    /// Stop callback: releases the memory cached by the calling thread, the
+    /// worker `index`, before it exits.
+    ///
+    /// The arena of the worker is kept for a worker started again with the
+    /// same index.
+    pub fn stop(&self, _index: usize) -> Result<()> {
+        thread::tcache_flush::invoke()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread as std_thread;
+
+    fn run(hooks: &Arc<Hooks>, index: usize) -> c_uint {
+        let hooks = hooks.clone();
+        std_thread::spawn(move || {
+            let arena = hooks.start(index).unwrap();
+            assert_eq!(thread::arena::read().unwrap(), arena);
+            hooks.park().unwrap();
+            hooks.stop(index).unwrap();
+            arena
+        })
+        .join()
+        .unwrap()
+    }
+
+    #[test]
+    fn round_robin() {
+        let hooks = Arc::new(Hooks::new(Policy::RoundRobin));
+        let narenas = opt::narenas::read().unwrap();
+        assert_eq!(run(&hooks, 0), 0);
+        assert_eq!(run(&hooks, narenas as usize + 1), 1 % narenas);
+    }
+
+    #[test]
+    fn dedicated() {
+        let _lock = ::test_lock();
+        let hooks = Arc::new(Hooks::new(Policy::Dedicated));
+        let a = run(&hooks, 0);
+        let b = run(&hooks, 1);
+        assert_ne!(a, b);
+        assert!(a >= opt::narenas::read().unwrap());
+        assert!(b >= opt::narenas::read().unwrap());
+        assert_eq!(run(&hooks, 0), a);
+    }
+
+    #[test]
+    fn shared() {
+        let _lock = ::test_lock();
+        let hooks = Arc::new(Hooks::new(Policy::Shared));
+        let a = run(&hooks, 0);
+        assert_eq!(run(&hooks, 7), a);
+        assert_ne!(Hooks::new(Policy::Shared).arena(0).unwrap(), a);
+    }
+
+    #[test]
+    fn next_index() {
+        let hooks = Hooks::new(Policy::RoundRobin);
+        assert_eq!(hooks.next_index(), 0);
+        assert_eq!(hooks.next_index(), 1);
+    }
+}
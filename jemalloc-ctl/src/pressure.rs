@@ -0,0 +1,631 @@
+//! Memory-pressure responder using Linux PSI and cgroup memory limits.
+//!
+//! A [`Responder`] reads the pressure stall information (PSI) of
+//! `/proc/pressure/memory` and the memory usage and limit of the cgroup of
+//! the process on a background thread at a fixed interval. As the pressure
+//! rises, it shortens the `arena.<i>.dirty_decay_ms` and
+//! `arena.<i>.muzzy_decay_ms` of the arenas, decays or purges their unused
+//! pages, and flushes the thread caches of the threads checking
+//! [`Flusher::flush_if_pressured`]. Once the pressure is gone, the decay
+//! times are restored.
+//!
+//! Both cgroup v2 (`memory.max` and `memory.current`) and cgroup v1
+//! (`memory.limit_in_bytes` and `memory.usage_in_bytes`) are supported. The
+//! cgroup of the process is read from `/proc/self/cgroup` and its files from
+//! the hierarchies mounted under `/sys/fs/cgroup`. The paths of the files are
+//! part of the [`Options`], and missing files are ignored, e.g., on kernels
+//! without PSI.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate jemallocator;
+//! extern crate jemalloc_ctl;
+//!
+//! use jemalloc_ctl::pressure::{Options, Responder};
+//!
+//! #[global_allocator]
+//! static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//!
+//! fn main() {
+//!     let responder = Responder::spawn_with_callback(
+//!         Options::default(),
+//!         |status| println!("memory pressure: {:?}", status.level),
+//!     )
+//!     .unwrap();
+//!
+//!     // the workers flush their thread caches when they become idle:
+//!     let flusher = responder.flusher();
+//!     std::thread::spawn(move || loop {
+//!         // ... work ...
+//!         flusher.flush_if_pressured();
+//!     });
+//!
+//!     // ... run the service ...
+//!
+//!     // Dropping the responder stops its thread and restores the decay
+//!     // times:
+//!     drop(responder);
+//! }
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::vec::Vec;
+
+use keys::{Access, AsName, Mib};
+use periodic::Periodic;
+use {arenas, thread};
+
+/// Memory-pressure level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// No pressure: the decay times of the arenas are left unchanged.
+    Normal,
+    /// The decay times are shortened to [`Options::moderate_decay_ms`] and
+    /// the unused pages are decayed at every interval.
+    Moderate,
+    /// The decay times are shortened to [`Options::critical_decay_ms`], the
+    /// unused pages are purged at every interval, and the thread caches are
+    /// flushed.
+    Critical,
+}
+
+impl Level {
+    fn from_usize(level: usize) -> Self {
+        match level {
+            0 => Level::Normal,
+            1 => Level::Moderate,
+            _ => Level::Critical,
+        }
+    }
+}
+
+/// Responder configuration.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Time between two readings.
+    ///
+    /// Defaults to 1 second.
+    pub interval: Duration,
+
+    /// PSI file, read for its `some avg10` value.
+    ///
+    /// Defaults to `/proc/pressure/memory`.
+    pub psi_path: Option<PathBuf>,
+
+    /// File with the memory limit of the cgroup in bytes.
+    ///
+    /// Defaults to the `memory.max` of the cgroup v2 of the process, e.g.,
+    /// `/sys/fs/cgroup/system.slice/app.service/memory.max`, if it exists,
+    /// and to the `memory.limit_in_bytes` of its cgroup v1 `memory`
+    /// controller, e.g., `/sys/fs/cgroup/memory/app/memory.limit_in_bytes`,
+    /// otherwise. If the cgroup of the process has no such file, e.g.,
+    /// because a container mounts its cgroup as the root of the hierarchy,
+    /// that of the root is used.
+    pub limit_path: Option<PathBuf>,
+
+    /// File with the memory usage of the cgroup in bytes.
+    ///
+    /// Defaults to the `memory.current` or `memory.usage_in_bytes` next to
+    /// the default [`Options::limit_path`].
+    pub usage_path: Option<PathBuf>,
+
+    /// Percentage of the time stalled on memory (`some avg10`) from which
+    /// the pressure is [`Level::Moderate`].
+    ///
+    /// Defaults to `10.0`.
+    pub psi_moderate: f64,
+
+    /// Percentage of the time stalled on memory (`some avg10`) from which
+    /// the pressure is [`Level::Critical`].
+    ///
+    /// Defaults to `40.0`.
+    pub psi_critical: f64,
+
+    /// Fraction of the cgroup limit in use from which the pressure is
+    /// [`Level::Moderate`].
+    ///
+    /// Defaults to `0.8`.
+    pub usage_moderate: f64,
+
+    /// Fraction of the cgroup limit in use from which the pressure is
+    /// [`Level::Critical`].
+    ///
+    /// Defaults to `0.95`.
+    pub usage_critical: f64,
+
+    /// Decay time of the dirty and muzzy pages under moderate pressure.
+    ///
+    /// Arenas with shorter decay times keep them, and `-1`, which disables
+    /// the decay, leaves the decay times unchanged. Defaults to 1000
+    /// milliseconds.
+    pub moderate_decay_ms: isize,
+
+    /// Decay time of the dirty and muzzy pages under critical pressure.
+    ///
+    /// Defaults to `0`, which purges the unused pages immediately.
+    pub critical_decay_ms: isize,
+
+    _p: (),
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        let cgroups =
+            fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        let (limit, usage) =
+            memory_files(Path::new("/sys/fs/cgroup"), &cgroups);
+        Self {
+            interval: Duration::from_secs(1),
+            psi_path: Some("/proc/pressure/memory".into()),
+            limit_path: Some(limit),
+            usage_path: Some(usage),
+            psi_moderate: 10.0,
+            psi_critical: 40.0,
+            usage_moderate: 0.8,
+            usage_critical: 0.95,
+            moderate_decay_ms: 1000,
+            critical_decay_ms: 0,
+            _p: (),
+        }
+    }
+}
+
+/// A reading of the pressure files.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Reading {
+    /// Percentage of the last 10 seconds in which some tasks were stalled
+    /// on memory, if the PSI file is readable.
+    pub psi_avg10: Option<f64>,
+    /// Memory usage of the cgroup in bytes, if the file is readable.
+    pub usage: Option<u64>,
+    /// Memory limit of the cgroup in bytes, if the file is readable and the
+    /// cgroup has a limit.
+    pub limit: Option<u64>,
+}
+
+impl Reading {
+    /// Reads the files of `options`.
+    pub fn read(options: &Options) -> Self {
+        let read = |path: &Option<PathBuf>| {
+            path.as_ref().and_then(|p| fs::read_to_string(p).ok())
+        };
+        Self {
+            psi_avg10: read(&options.psi_path).and_then(|s| parse_psi(&s)),
+            usage: read(&options.usage_path).and_then(|s| parse_bytes(&s)),
+            limit: read(&options.limit_path).and_then(|s| parse_bytes(&s)),
+        }
+    }
+
+    /// Returns the pressure level of the reading according to the
+    /// thresholds of `options`.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_precision_loss))]
+    pub fn level(&self, options: &Options) -> Level {
+        let psi = match self.psi_avg10 {
+            Some(p) if p >= options.psi_critical => Level::Critical,
+            Some(p) if p >= options.psi_moderate => Level::Moderate,
+            _ => Level::Normal,
+        };
+        let usage = match (self.usage, self.limit) {
+            (Some(usage), Some(limit)) if limit > 0 => {
+                let used = usage as f64 / limit as f64;
+                if used >= options.usage_critical {
+                    Level::Critical
+                } else if used >= options.usage_moderate {
+                    Level::Moderate
+                } else {
+                    Level::Normal
+                }
+            }
+            _ => Level::Normal,
+        };
+        psi.max(usage)
+    }
+}
+
+/// Returns the memory limit and usage files of the cgroup of the process,
+/// given the mount point `mount` of the cgroup hierarchies and the contents
+/// `cgroups` of `/proc/self/cgroup`, see [`Options::limit_path`].
+fn memory_files(mount: &Path, cgroups: &str) -> (PathBuf, PathBuf) {
+    let v2 = ("memory.max", "memory.current");
+    let v1 = ("memory.limit_in_bytes", "memory.usage_in_bytes");
+    let memory = mount.join("memory");
+    let candidates = [
+        (mount.join(cgroup_path(cgroups, None)), v2),
+        (mount.to_path_buf(), v2),
+        (memory.join(cgroup_path(cgroups, Some("memory"))), v1),
+        (memory, v1),
+    ];
+    let &(ref dir, (limit, usage)) = candidates
+        .iter()
+        .find(|&&(ref dir, (limit, _))| dir.join(limit).exists())
+        .unwrap_or(&candidates[3]);
+    (dir.join(limit), dir.join(usage))
+}
+
+/// Returns the path of the cgroup of the process relative to the root of its
+/// hierarchy, given the contents `cgroups` of `/proc/self/cgroup`: that of
+/// cgroup v2 for `None`, and that of the cgroup v1 hierarchy of `controller`
+/// otherwise.
+///
+/// Returns the root, i.e., an empty path, if the cgroup is not listed.
+fn cgroup_path<'a>(cgroups: &'a str, controller: Option<&str>) -> &'a str {
+    cgroups
+        .lines()
+        .filter_map(|line| {
+            // `<id>:<controllers>:<path>`, with no controllers for cgroup v2:
+            let mut fields = line.splitn(3, ':');
+            let _id = fields.next()?;
+            let controllers = fields.next()?;
+            let path = fields.next()?;
+            let listed = match controller {
+                None => controllers.is_empty(),
+                Some(c) => controllers.split(',').any(|name| name == c),
+            };
+            if listed {
+                Some(path.trim_start_matches('/'))
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap_or("")
+}
+
+/// Parses the `avg10` value of the `some` line of a PSI file.
+fn parse_psi(s: &str) -> Option<f64> {
+    let line = s.lines().find(|l| l.starts_with("some "))?;
+    let avg10 = line.split_whitespace().find(|f| f.starts_with("avg10="))?;
+    avg10["avg10=".len()..].parse().ok()
+}
+
+/// Parses a cgroup memory file, returning `None` for unlimited values.
+fn parse_bytes(s: &str) -> Option<u64> {
+    // cgroup v1 reports no limit as the largest page-aligned `i64`:
+    s.trim().parse().ok().filter(|&v| v < 1 << 62)
+}
+
+/// A reading and the level it corresponds to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Status {
+    /// The reading of the pressure files.
+    pub reading: Reading,
+    /// The pressure level of the reading.
+    pub level: Level,
+}
+
+/// Flushes the thread cache of the calling thread under critical pressure.
+///
+/// The thread caches can only be flushed by their threads, see
+/// [`thread::tcache_flush`]. Obtained with [`Responder::flusher`].
+#[derive(Clone)]
+pub struct Flusher {
+    level: Arc<AtomicUsize>,
+}
+
+impl Flusher {
+    /// Returns the current pressure level.
+    pub fn level(&self) -> Level {
+        Level::from_usize(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Flushes the thread cache of the calling thread if the pressure is
+    /// [`Level::Critical`], and returns whether it did.
+    pub fn flush_if_pressured(&self) -> bool {
+        self.level() == Level::Critical
+            && thread::tcache_flush::invoke().is_ok()
+    }
+}
+
+/// The decay times of the arenas before they were shortened.
+struct Decay {
+    dirty: Mib<[usize; 3]>,
+    muzzy: Mib<[usize; 3]>,
+    /// Arena index, dirty and muzzy decay times.
+    saved: Vec<(usize, isize, isize)>,
+}
+
+impl Decay {
+    fn new() -> ::error::Result<Self> {
+        Ok(Self {
+            dirty: b"arena.0.dirty_decay_ms\0".name().mib()?,
+            muzzy: b"arena.0.muzzy_decay_ms\0".name().mib()?,
+            saved: Vec::new(),
+        })
+    }
+
+    /// Shortens the decay times of all initialized arenas to at most `ms`,
+    /// saving their previous values the first time.
+    fn shorten(&mut self, ms: isize) {
+        let narenas = match arenas::narenas::read() {
+            Ok(n) => n as usize,
+            Err(_) => return,
+        };
+        for arena in 0..narenas {
+            let (mut dirty, mut muzzy) = (self.dirty, self.muzzy);
+            dirty[1] = arena;
+            muzzy[1] = arena;
+            // Uninitialized arenas fail:
+            let (d, m) = match (dirty.read(), muzzy.read()) {
+                (Ok(d), Ok(m)) => (d, m),
+                _ => continue,
+            };
+            let _ = dirty.write(shorter(d, ms));
+            let _ = muzzy.write(shorter(m, ms));
+            if self.saved.iter().all(|&(a, _, _)| a != arena) {
+                self.saved.push((arena, d, m));
+            }
+        }
+    }
+
+    /// Restores the saved decay times.
+    fn restore(&mut self) {
+        for (arena, d, m) in self.saved.drain(..) {
+            let (mut dirty, mut muzzy) = (self.dirty, self.muzzy);
+            dirty[1] = arena;
+            muzzy[1] = arena;
+            let _ = dirty.write(d);
+            let _ = muzzy.write(m);
+        }
+    }
+}
+
+/// Returns the shorter of the decay times `a` and `b`, where `-1` disables
+/// the decay.
+fn shorter(a: isize, b: isize) -> isize {
+    match (a, b) {
+        (-1, ms) | (ms, -1) => ms,
+        (a, b) => a.min(b),
+    }
+}
+
+/// Responds to memory pressure on a background thread.
+///
+/// The thread is named `jemalloc-pressure` and it takes the first reading
+/// immediately. Dropping the `Responder` stops the thread, waits for it to
+/// finish, and restores the decay times of the arenas.
+pub struct Responder {
+    level: Arc<AtomicUsize>,
+    decay: Arc<Mutex<Decay>>,
+    thread: Option<Periodic>,
+}
+
+impl Responder {
+    /// Spawns a responder.
+    pub fn spawn(options: Options) -> io::Result<Self> {
+        Self::spawn_with_callback(options, |_| ())
+    }
+
+    /// Spawns a responder calling `callback` on its thread with the status
+    /// of each reading.
+    pub fn spawn_with_callback<F>(
+        options: Options,
+        mut callback: F,
+    ) -> io::Result<Self>
+    where
+        F: FnMut(&Status) + Send + 'static,
+    {
+        let decay = Decay::new()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let decay = Arc::new(Mutex::new(decay));
+        let level = Arc::new(AtomicUsize::new(Level::Normal as usize));
+        let (thread_decay, thread_level) = (decay.clone(), level.clone());
+        let thread = Periodic::spawn(
+            "jemalloc-pressure",
+            options.interval,
+            move || {
+                let reading = Reading::read(&options);
+                let status = Status {
+                    reading,
+                    level: reading.level(&options),
+                };
+                respond(
+                    &options,
+                    status.level,
+                    &mut thread_decay.lock().unwrap(),
+                );
+                // The level is stored after the actions, so that they have
+                // been applied once it is observed:
+                thread_level.store(status.level as usize, Ordering::Relaxed);
+                callback(&status);
+            },
+        )?;
+        Ok(Self {
+            level,
+            decay,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the pressure level of the latest reading.
+    pub fn level(&self) -> Level {
+        Level::from_usize(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Returns a handle flushing the thread caches of the threads using it
+    /// under critical pressure.
+    pub fn flusher(&self) -> Flusher {
+        Flusher {
+            level: self.level.clone(),
+        }
+    }
+}
+
+impl Drop for Responder {
+    fn drop(&mut self) {
+        // Stop the thread first, so that it cannot shorten the decay times
+        // again:
+        self.thread.take();
+        self.decay.lock().unwrap().restore();
+    }
+}
+
+/// Applies the actions of `level`; failures are retried at the next
+/// interval.
+fn respond(options: &Options, level: Level, decay: &mut Decay) {
+    match level {
+        Level::Normal => decay.restore(),
+        Level::Moderate => {
+            decay.shorten(options.moderate_decay_ms);
+            let _ = ::decay_all();
+        }
+        Level::Critical => {
+            decay.shorten(options.critical_decay_ms);
+            let _ = ::purge_all();
+            let _ = thread::tcache_flush::invoke();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::thread as std_thread;
+    use std::time::Instant;
+
+    const PSI: &str = "some avg10=12.50 avg60=3.00 avg300=1.00 total=1234\n\
+                       full avg10=2.00 avg60=0.50 avg300=0.10 total=567\n";
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_psi(PSI), Some(12.5));
+        assert_eq!(parse_psi("full avg10=2.00\n"), None);
+        assert_eq!(parse_bytes("1073741824\n"), Some(1 << 30));
+        assert_eq!(parse_bytes("max\n"), None);
+        assert_eq!(parse_bytes("9223372036854771712\n"), None);
+    }
+
+    #[test]
+    fn cgroup_paths() {
+        let cgroups = "4:memory:/app/worker\n\
+                       2:cpu,cpuacct:/app\n\
+                       1:name=systemd:/user.slice\n\
+                       0::/system.slice/app.service\n";
+        assert_eq!(cgroup_path(cgroups, None), "system.slice/app.service");
+        assert_eq!(cgroup_path(cgroups, Some("memory")), "app/worker");
+        assert_eq!(cgroup_path(cgroups, Some("cpuacct")), "app");
+        assert_eq!(cgroup_path(cgroups, Some("cpuset")), "");
+        assert_eq!(cgroup_path("0::/\n", None), "");
+        assert_eq!(cgroup_path("", None), "");
+    }
+
+    #[test]
+    fn memory_files_of_the_process() {
+        let mount = env::temp_dir()
+            .join(format!("jemalloc-ctl-cgroup-{}", ::std::process::id()));
+        let v2 = mount.join("system.slice/app.service");
+        let v1 = mount.join("memory/app");
+        fs::create_dir_all(&v2).unwrap();
+        fs::create_dir_all(&v1).unwrap();
+        let cgroups = "4:memory:/app\n0::/system.slice/app.service\n";
+
+        // Without the files of cgroup v2, those of cgroup v1 are used:
+        fs::write(v1.join("memory.limit_in_bytes"), "1000\n").unwrap();
+        assert_eq!(
+            memory_files(&mount, cgroups),
+            (
+                v1.join("memory.limit_in_bytes"),
+                v1.join("memory.usage_in_bytes")
+            )
+        );
+        fs::write(v2.join("memory.max"), "1000\n").unwrap();
+        assert_eq!(
+            memory_files(&mount, cgroups),
+            (v2.join("memory.max"), v2.join("memory.current"))
+        );
+        // A cgroup mounted as the root of the hierarchy, e.g., in a
+        // container:
+        fs::write(mount.join("memory.max"), "1000\n").unwrap();
+        assert_eq!(
+            memory_files(&mount, "0::/other\n"),
+            (mount.join("memory.max"), mount.join("memory.current"))
+        );
+        // Without any file, the cgroup v1 files of the root are used:
+        assert_eq!(
+            memory_files(&mount.join("none"), cgroups),
+            (
+                mount.join("none/memory/memory.limit_in_bytes"),
+                mount.join("none/memory/memory.usage_in_bytes")
+            )
+        );
+        fs::remove_dir_all(mount).unwrap();
+    }
+
+    #[test]
+    fn levels() {
+        let options = Options::default();
+        let mut r = Reading::default();
+        assert_eq!(r.level(&options), Level::Normal);
+        r.psi_avg10 = Some(12.5);
+        assert_eq!(r.level(&options), Level::Moderate);
+        r.usage = Some(96);
+        assert_eq!(r.level(&options), Level::Moderate);
+        r.limit = Some(100);
+        assert_eq!(r.level(&options), Level::Critical);
+        r.psi_avg10 = None;
+        r.usage = Some(10);
+        assert_eq!(r.level(&options), Level::Normal);
+    }
+
+    fn wait_for(responder: &Responder, level: Level) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while responder.level() != level {
+            assert!(Instant::now() < deadline, "timed out");
+            std_thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn shorter_decay() {
+        assert_eq!(shorter(-1, 100), 100);
+        assert_eq!(shorter(100, -1), 100);
+        assert_eq!(shorter(50, 100), 50);
+        assert_eq!(shorter(-1, -1), -1);
+        assert_eq!(shorter(0, -1), 0);
+    }
+
+    #[test]
+    fn responder() {
+        // The responder changes the decay times of all the arenas:
+        let _lock = ::test_lock();
+        let dir = env::temp_dir()
+            .join(format!("jemalloc-ctl-pressure-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (psi, limit, usage) =
+            (dir.join("memory"), dir.join("max"), dir.join("current"));
+        fs::write(&psi, "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n")
+            .unwrap();
+        fs::write(&limit, "1000\n").unwrap();
+        fs::write(&usage, "850\n").unwrap();
+
+        let mut options = Options::default();
+        options.interval = Duration::from_millis(10);
+        options.psi_path = Some(psi.clone());
+        options.limit_path = Some(limit);
+        options.usage_path = Some(usage.clone());
+
+        let decay_ms = b"arena.0.dirty_decay_ms\0".name();
+        let before: isize = decay_ms.read().unwrap();
+        options.moderate_decay_ms = shorter(before, 10_000) / 2;
+        let responder = Responder::spawn(options.clone()).unwrap();
+        wait_for(&responder, Level::Moderate);
+        assert_eq!(decay_ms.read(), Ok(options.moderate_decay_ms));
+        assert!(!responder.flusher().flush_if_pressured());
+
+        fs::write(&usage, "100\n").unwrap();
+        wait_for(&responder, Level::Normal);
+        assert_eq!(decay_ms.read(), Ok(before));
+
+        fs::write(&psi, PSI).unwrap();
+        wait_for(&responder, Level::Moderate);
+        drop(responder);
+        assert_eq!(decay_ms.read(), Ok(before));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
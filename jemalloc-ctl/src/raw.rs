@@ -200,6 +200,46 @@ pub unsafe fn update<T>(name: &[u8], mut value: T) -> Result<T> {
     Ok(value)
 }
 
+/// Uses the MIB `mib` as key to the _MALLCTL NAMESPACE_ and performs its
+/// operation without reading or writing a value, e.g., `thread.tcache.flush`.
+///
+/// The [`name_to_mib`] API translates a string of the key (e.g. `arenas.nbins`)
+/// to a `mib` (Management Information Base).
+///
+/// # Safety
+///
+/// This function is `unsafe` because some operations invalidate memory that
+/// is still in use, for example, `arena.<i>.reset` and `arena.<i>.destroy`
+/// free all the allocations of the arena.
+pub unsafe fn invoke_mib(mib: &[usize]) -> Result<()> {
+    cvt(jemalloc_sys::mallctlbymib(
+        mib.as_ptr(),
+        mib.len(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        0,
+    ))
+}
+
+/// Uses the null-terminated string `name` as key to the _MALLCTL NAMESPACE_ and
+/// performs its operation without reading or writing a value.
+///
+/// # Safety
+///
+/// See [`invoke_mib`].
+pub unsafe fn invoke(name: &[u8]) -> Result<()> {
+    validate_name(name);
+
+    cvt(jemalloc_sys::mallctl(
+        name as *const _ as *const c_char,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+        0,
+    ))
+}
+
 /// Uses the MIB `mib` as key to the _MALLCTL NAMESPACE_ and reads its value.
 ///
 /// The [`name_to_mib`] API translates a string of the key (e.g. `arenas.nbins`)
@@ -0,0 +1,138 @@
+//! Releasing unused memory of the arenas to the operating system.
+//!
+//! `jemalloc` keeps the pages of freed extents as dirty pages, which it
+//! gradually purges to muzzy pages and then to the operating system, see
+//! `opt.dirty_decay_ms`. The functions of this module purge them on
+//! demand, e.g., after large batch jobs or on memory pressure, and return
+//! the number of bytes released by diffing `stats.arenas.<i>.pdirty` and
+//! `stats.arenas.<i>.pmuzzy` around the call.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate jemallocator;
+//! # extern crate jemalloc_ctl;
+//! #
+//! # #[global_allocator]
+//! # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//! #
+//! # fn main() {
+//! let batch = vec![0_u8; 64 << 20];
+//! drop(batch);
+//! let released = jemalloc_ctl::purge_all().unwrap();
+//! println!("released {} bytes", released.total());
+//! # }
+//! ```
+
+use error::Result;
+use keys::{Access, AsName, Mib};
+use raw;
+
+/// Bytes of unused pages released by [`purge`] or [`decay`].
+///
+/// Pages released concurrently by other threads, e.g., background threads,
+/// are included, and pages freed concurrently reduce the counts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Released {
+    /// Bytes of dirty pages released, see `stats.arenas.<i>.pdirty`.
+    pub dirty_bytes: usize,
+    /// Bytes of muzzy pages released, see `stats.arenas.<i>.pmuzzy`.
+    pub muzzy_bytes: usize,
+}
+
+impl Released {
+    /// Bytes of dirty and muzzy pages released.
+    pub fn total(&self) -> usize {
+        self.dirty_bytes + self.muzzy_bytes
+    }
+}
+
+/// Purges all the unused dirty and muzzy pages of the arena `arena`, see
+/// `arena.<i>.purge`.
+///
+/// Passing [`jemalloc_sys::MALLCTL_ARENAS_ALL`] purges all arenas, see
+/// [`purge_all`].
+pub fn purge(arena: usize) -> Result<Released> {
+    release(b"arena.0.purge\0", arena)
+}
+
+/// Purges the unused dirty and muzzy pages of the arena `arena` whose decay
+/// time has elapsed, see `arena.<i>.decay`.
+///
+/// Passing [`jemalloc_sys::MALLCTL_ARENAS_ALL`] purges all arenas, see
+/// [`decay_all`].
+pub fn decay(arena: usize) -> Result<Released> {
+    release(b"arena.0.decay\0", arena)
+}
+
+/// Purges all the unused dirty and muzzy pages of all arenas, see [`purge`].
+pub fn purge_all() -> Result<Released> {
+    purge(jemalloc_sys::MALLCTL_ARENAS_ALL)
+}
+
+/// Purges the unused dirty and muzzy pages of all arenas whose decay time
+/// has elapsed, see [`decay`].
+pub fn decay_all() -> Result<Released> {
+    decay(jemalloc_sys::MALLCTL_ARENAS_ALL)
+}
+
+/// Invokes the `arena.<i>.*` key `name` for the arena `arena`, returning the
+/// bytes released.
+fn release(name: &[u8], arena: usize) -> Result<Released> {
+    let mut mib: Mib<[usize; 3]> = name.name().mib()?;
+    mib[1] = arena;
+    let mut pdirty: Mib<[usize; 4]> =
+        b"stats.arenas.0.pdirty\0".name().mib()?;
+    pdirty[2] = arena;
+    let mut pmuzzy: Mib<[usize; 4]> =
+        b"stats.arenas.0.pmuzzy\0".name().mib()?;
+    pmuzzy[2] = arena;
+    let epoch = ::epoch::mib()?;
+    let page = ::arenas::page::read()?;
+
+    epoch.advance()?;
+    let (dirty, muzzy): (usize, usize) = (pdirty.read()?, pmuzzy.read()?);
+    unsafe { raw::invoke_mib(mib.as_ref())? };
+    epoch.advance()?;
+    let (dirty_after, muzzy_after): (usize, usize) =
+        (pdirty.read()?, pmuzzy.read()?);
+    Ok(Released {
+        dirty_bytes: dirty.saturating_sub(dirty_after) * page,
+        muzzy_bytes: muzzy.saturating_sub(muzzy_after) * page,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn purge_all_releases_dirty_pages() {
+        let _lock = ::test_lock();
+        // Disable decay so that the freed pages are left dirty:
+        let decay_ms = b"arenas.dirty_decay_ms\0".name();
+        let default: libc::ssize_t = decay_ms.update(-1).unwrap();
+        let arena = ::arenas::create::read().unwrap();
+        decay_ms.write(default).unwrap();
+        let flags = jemalloc_sys::MALLOCX_ARENA(arena as usize)
+            | jemalloc_sys::MALLOCX_TCACHE_NONE();
+        unsafe {
+            let ptr = jemalloc_sys::mallocx(4 << 20, flags);
+            jemalloc_sys::dallocx(ptr, flags);
+        }
+        let released = purge(arena as usize).unwrap();
+        assert!(released.dirty_bytes > 0);
+        assert_eq!(purge(arena as usize).unwrap().dirty_bytes, 0);
+        assert!(purge_all().is_ok());
+        assert!(decay_all().is_ok());
+    }
+
+    #[test]
+    fn released_total() {
+        let r = Released {
+            dirty_bytes: 4096,
+            muzzy_bytes: 8192,
+        };
+        assert_eq!(r.total(), 12288);
+    }
+}
@@ -0,0 +1,336 @@
+//! Periodic background statistics reporter.
+//!
+//! A [`Reporter`] samples the allocator statistics on a background thread at
+//! a fixed interval, keeps the most recent samples in a ring buffer, and
+//! computes allocation, deallocation, and purge rates between consecutive
+//! samples. Besides the global statistics, the [`Options`] select whether the
+//! merged statistics of all arenas and those of individual arenas are
+//! sampled. The samples can be queried by the application or passed to a
+//! callback as they are taken.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate jemallocator;
+//! extern crate jemalloc_ctl;
+//!
+//! use std::thread;
+//! use std::time::Duration;
+//! use jemalloc_ctl::reporter::{Options, Reporter};
+//!
+//! #[global_allocator]
+//! static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+//!
+//! fn main() {
+//!     let mut options = Options::default();
+//!     options.interval = Duration::from_secs(1);
+//!     let reporter = Reporter::spawn_with_callback(options, |s| {
+//!         if let Some(r) = s.rates {
+//!             println!("{:.0} allocations/s", r.allocations);
+//!         }
+//!     })
+//!     .unwrap();
+//!
+//!     thread::sleep(Duration::from_secs(10));
+//!     let latest = reporter.latest().unwrap();
+//!     println!("{} bytes allocated", latest.snapshot.allocated);
+//!
+//!     // Dropping the reporter stops its thread:
+//!     drop(reporter);
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use periodic::Periodic;
+use stats::{ArenaSnapshot, Snapshot, SnapshotMib};
+
+/// Reporter configuration.
+#[derive(Clone)]
+pub struct Options {
+    /// Time between two samples.
+    ///
+    /// Defaults to 10 seconds.
+    pub interval: Duration,
+
+    /// Number of samples kept in the ring buffer.
+    ///
+    /// Defaults to 60.
+    pub capacity: usize,
+
+    /// If set, the merged statistics of all arenas will not be sampled.
+    ///
+    /// The rates are computed from these statistics, so they are not
+    /// available either. Defaults to `false`.
+    pub skip_merged_arenas: bool,
+
+    /// Indices of the arenas whose statistics are sampled individually, see
+    /// [`Sample::arenas`].
+    ///
+    /// Defaults to none.
+    pub arenas: Vec<usize>,
+
+    _p: (),
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            capacity: 60,
+            skip_merged_arenas: false,
+            arenas: Vec::new(),
+            _p: (),
+        }
+    }
+}
+
+/// Rates of change between two consecutive samples, in events per second.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rates {
+    /// Small and large allocation requests per second.
+    pub allocations: f64,
+    /// Small and large deallocation requests per second.
+    pub deallocations: f64,
+    /// Dirty and muzzy pages purged per second.
+    pub purged_pages: f64,
+}
+
+/// A sample of the allocator statistics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// Time at which the sample was taken.
+    pub time: Instant,
+    /// Global statistics.
+    pub snapshot: Snapshot,
+    /// Merged statistics of all arenas, unless
+    /// [`Options::skip_merged_arenas`] is set.
+    pub merged: Option<ArenaSnapshot>,
+    /// Statistics of the arenas of [`Options::arenas`], in the same order.
+    ///
+    /// Arenas that are not initialized, e.g., automatic arenas no thread has
+    /// been assigned to yet, are left out.
+    pub arenas: Vec<ArenaSnapshot>,
+    /// Rates since the previous sample, if there is a previous sample and
+    /// the merged statistics are sampled.
+    pub rates: Option<Rates>,
+}
+
+/// Samples the allocator statistics periodically on a background thread.
+///
+/// The thread is named `jemalloc-reporter` and it takes the first sample
+/// immediately. Dropping the `Reporter` stops the thread and waits for it to
+/// finish.
+pub struct Reporter {
+    samples: Arc<Mutex<VecDeque<Sample>>>,
+    _thread: Periodic,
+}
+
+impl Reporter {
+    /// Spawns a reporter.
+    pub fn spawn(options: Options) -> io::Result<Self> {
+        Self::spawn_with_callback(options, |_| ())
+    }
+
+    /// Spawns a reporter calling `callback` on its thread with each new
+    /// sample.
+    ///
+    /// # Panics
+    ///
+    /// If `options.capacity` is zero.
+    pub fn spawn_with_callback<F>(
+        options: Options,
+        mut callback: F,
+    ) -> io::Result<Self>
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        assert!(options.capacity > 0, "the capacity cannot be zero");
+        let mib = Snapshot::mib()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let samples =
+            Arc::new(Mutex::new(VecDeque::with_capacity(options.capacity)));
+        let thread_samples = samples.clone();
+        let mut previous = None;
+        let thread = Periodic::spawn(
+            "jemalloc-reporter",
+            options.interval,
+            move || {
+                // Failing to read the statistics skips this sample:
+                let sample = match sample(&mib, &options, previous.as_ref()) {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                {
+                    let mut samples = thread_samples.lock().unwrap();
+                    if samples.len() == options.capacity {
+                        samples.pop_front();
+                    }
+                    samples.push_back(sample.clone());
+                }
+                callback(&sample);
+                previous = Some(sample);
+            },
+        )?;
+        Ok(Self {
+            samples,
+            _thread: thread,
+        })
+    }
+
+    /// Returns the most recent sample.
+    pub fn latest(&self) -> Option<Sample> {
+        self.samples.lock().unwrap().back().cloned()
+    }
+
+    /// Returns the samples in the ring buffer, oldest first.
+    pub fn samples(&self) -> Vec<Sample> {
+        self.samples.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn sample(
+    mib: &SnapshotMib,
+    options: &Options,
+    previous: Option<&Sample>,
+) -> ::error::Result<Sample> {
+    let snapshot = mib.take()?;
+    let time = Instant::now();
+    let merged = if options.skip_merged_arenas {
+        None
+    } else {
+        Some(mib.arena(::jemalloc_sys::MALLCTL_ARENAS_ALL)?)
+    };
+    let arenas = options
+        .arenas
+        .iter()
+        .filter_map(|&arena| mib.arena(arena).ok())
+        .collect();
+    let rates = match (previous, merged) {
+        (
+            Some(&Sample {
+                time: t,
+                merged: Some(ref p),
+                ..
+            }),
+            Some(ref c),
+        ) => Some(rates(p, c, time.duration_since(t))),
+        _ => None,
+    };
+    Ok(Sample {
+        time,
+        snapshot,
+        merged,
+        arenas,
+        rates,
+    })
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_precision_loss))]
+fn rates(p: &ArenaSnapshot, c: &ArenaSnapshot, elapsed: Duration) -> Rates {
+    let secs =
+        elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+    if secs == 0.0 {
+        return Rates::default();
+    }
+    let per_sec = |p: u64, c: u64| c.saturating_sub(p) as f64 / secs;
+    Rates {
+        allocations: per_sec(
+            p.small_nmalloc + p.large_nmalloc,
+            c.small_nmalloc + c.large_nmalloc,
+        ),
+        deallocations: per_sec(
+            p.small_ndalloc + p.large_ndalloc,
+            c.small_ndalloc + c.large_ndalloc,
+        ),
+        purged_pages: per_sec(
+            p.dirty_purged + p.muzzy_purged,
+            c.dirty_purged + c.muzzy_purged,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn options() -> Options {
+        let mut options = Options::default();
+        options.interval = Duration::from_millis(10);
+        options.capacity = 3;
+        options
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let (tx, rx) = mpsc::channel();
+        let reporter = Reporter::spawn_with_callback(options(), move |s| {
+            let _ = tx.send(s.clone());
+        })
+        .unwrap();
+
+        let first = rx.recv().unwrap();
+        assert!(first.merged.is_some());
+        assert!(first.arenas.is_empty());
+        assert!(first.rates.is_none());
+        for _ in 0..5 {
+            let s = rx.recv().unwrap();
+            assert!(s.rates.is_some());
+        }
+
+        let samples = reporter.samples();
+        assert_eq!(samples.len(), 3);
+        assert!(samples[0].time <= samples[1].time);
+        assert!(samples[0].snapshot.epoch < samples[2].snapshot.epoch);
+        assert!(reporter.latest().is_some());
+    }
+
+    #[test]
+    fn skip_merged_arenas() {
+        let mut options = options();
+        options.skip_merged_arenas = true;
+        let reporter = Reporter::spawn(options).unwrap();
+        while reporter.samples().len() < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let s = reporter.latest().unwrap();
+        assert!(s.merged.is_none());
+        assert!(s.rates.is_none());
+    }
+
+    #[test]
+    fn arenas() {
+        let mut options = options();
+        // Arena 0 is always initialized, unlike an out-of-range arena:
+        options.arenas = vec![1 << 20, 0];
+        let reporter = Reporter::spawn(options).unwrap();
+        while reporter.latest().is_none() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let s = reporter.latest().unwrap();
+        assert_eq!(s.arenas.len(), 1);
+        assert_eq!(s.arenas[0].arena, 0);
+    }
+
+    #[test]
+    fn rates_per_second() {
+        let mut p = ArenaSnapshot::default();
+        p.small_nmalloc = 10;
+        p.dirty_purged = 4;
+        let mut c = p;
+        c.small_nmalloc = 30;
+        c.large_nmalloc = 20;
+        c.large_ndalloc = 8;
+        c.muzzy_purged = 2;
+        let r = rates(&p, &c, Duration::from_secs(2));
+        assert_eq!(r.allocations, 20.0);
+        assert_eq!(r.deallocations, 4.0);
+        assert_eq!(r.purged_pages, 1.0);
+    }
+}
@@ -4,6 +4,9 @@
 //! only refreshed when the `jemalloc` "epoch" is advanced. See the [`::epoch`] type
 //! for more information.
 
+use error::{cvt, Result};
+use keys::{Access, AsName, Mib, MibArg};
+
 option! {
     allocated[ str: b"stats.allocated\0", non_str: 2 ] => libc::size_t |
     ops: r |
@@ -226,3 +229,593 @@ option! {
     /// ```
     mib_docs: /// See [`retained`].
 }
+
+option! {
+    background_thread_num_threads[ str: b"stats.background_thread.num_threads\0", non_str: 3 ] => libc::size_t |
+    ops: r |
+    docs:
+    /// Number of background threads running currently.
+    ///
+    /// This statistic is cached, and is only refreshed when the epoch is
+    /// advanced. See the [`::epoch`] type for more information.
+    ///
+    /// This corresponds to `stats.background_thread.num_threads` in jemalloc's API.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::{epoch, stats};
+    /// let e = epoch::mib().unwrap();
+    /// let background_thread_num_threads = stats::background_thread_num_threads::mib().unwrap();
+    ///
+    /// e.advance().unwrap();
+    /// let n = background_thread_num_threads.read().unwrap();
+    /// println!("{} background threads", n);
+    /// # }
+    /// ```
+    mib_docs: /// See [`background_thread_num_threads`].
+}
+
+option! {
+    background_thread_num_runs[ str: b"stats.background_thread.num_runs\0", non_str: 3 ] => u64 |
+    ops: r |
+    docs:
+    /// Total number of runs of all background threads.
+    ///
+    /// This statistic is cached, and is only refreshed when the epoch is
+    /// advanced. See the [`::epoch`] type for more information.
+    ///
+    /// This corresponds to `stats.background_thread.num_runs` in jemalloc's API.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::{epoch, stats};
+    /// let e = epoch::mib().unwrap();
+    /// let background_thread_num_runs = stats::background_thread_num_runs::mib().unwrap();
+    ///
+    /// e.advance().unwrap();
+    /// let runs = background_thread_num_runs.read().unwrap();
+    /// println!("{} background thread runs", runs);
+    /// # }
+    /// ```
+    mib_docs: /// See [`background_thread_num_runs`].
+}
+
+option! {
+    background_thread_run_interval[ str: b"stats.background_thread.run_interval\0", non_str: 3 ] => u64 |
+    ops: r |
+    docs:
+    /// Average run interval of the background threads in nanoseconds.
+    ///
+    /// This statistic is cached, and is only refreshed when the epoch is
+    /// advanced. See the [`::epoch`] type for more information.
+    ///
+    /// This corresponds to `stats.background_thread.run_interval` in jemalloc's API.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::{epoch, stats};
+    /// let e = epoch::mib().unwrap();
+    /// let background_thread_run_interval = stats::background_thread_run_interval::mib().unwrap();
+    ///
+    /// e.advance().unwrap();
+    /// let ns = background_thread_run_interval.read().unwrap();
+    /// println!("background threads run every {} ns", ns);
+    /// # }
+    /// ```
+    mib_docs: /// See [`background_thread_run_interval`].
+}
+
+/// Epoch-consistent snapshot of the global allocator statistics.
+///
+/// Reading the individual statistics of this module separately requires
+/// advancing the [`::epoch`] first, and concurrent epoch advances between the
+/// reads can produce values that do not belong together. A `Snapshot`
+/// advances the epoch once and reads all global statistics through cached
+/// MIBs right afterwards.
+///
+/// Per-arena and per-bin statistics can be read at the epoch of the last
+/// snapshot via [`SnapshotMib::arena`] and [`SnapshotMib::bin`].
+///
+/// # Examples
+///
+/// ```
+/// # extern crate jemallocator;
+/// # extern crate jemalloc_ctl;
+/// #
+/// # #[global_allocator]
+/// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+/// #
+/// # fn main() {
+/// use jemalloc_ctl::stats::Snapshot;
+/// let s = Snapshot::take().unwrap();
+/// assert!(s.allocated <= s.active);
+/// assert!(s.active <= s.resident);
+/// println!(
+///     "{} bytes allocated, fragmentation ratio: {:.2}",
+///     s.allocated,
+///     s.fragmentation_ratio()
+/// );
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    /// Epoch at which the statistics were read.
+    pub epoch: u64,
+    /// See [`allocated`].
+    pub allocated: libc::size_t,
+    /// See [`active`].
+    pub active: libc::size_t,
+    /// See [`metadata`].
+    pub metadata: libc::size_t,
+    /// See [`resident`].
+    pub resident: libc::size_t,
+    /// See [`mapped`].
+    pub mapped: libc::size_t,
+    /// See [`retained`].
+    pub retained: libc::size_t,
+    /// Number of dirty pages in all arenas (`stats.arenas.<i>.pdirty` of
+    /// [`jemalloc_sys::MALLCTL_ARENAS_ALL`]).
+    pub dirty_pages: libc::size_t,
+    /// Number of muzzy pages in all arenas (`stats.arenas.<i>.pmuzzy` of
+    /// [`jemalloc_sys::MALLCTL_ARENAS_ALL`]).
+    pub muzzy_pages: libc::size_t,
+    /// Page size in bytes, see [`::arenas::page`].
+    pub page_size: libc::size_t,
+}
+
+impl Snapshot {
+    /// Advances the epoch and takes a snapshot using the string API to
+    /// obtain the MIBs.
+    ///
+    /// If snapshots are taken repeatedly, obtain a [`SnapshotMib`] once via
+    /// [`Snapshot::mib`] and use [`SnapshotMib::take`] instead.
+    pub fn take() -> Result<Self> {
+        Self::mib()?.take()
+    }
+
+    /// Returns the Management Information Base (MIB) of all keys read by
+    /// a snapshot.
+    pub fn mib() -> Result<SnapshotMib> {
+        SnapshotMib::new()
+    }
+
+    /// Ratio of [`active`] to [`allocated`] bytes.
+    ///
+    /// A value of `1.0` means that all active pages are used by allocations;
+    /// larger values indicate fragmentation. Returns `0.0` if nothing is
+    /// allocated.
+    pub fn fragmentation_ratio(&self) -> f64 {
+        ratio(self.active, self.allocated)
+    }
+
+    /// Number of bytes in active pages that are not used by allocations.
+    pub fn fragmented_bytes(&self) -> libc::size_t {
+        self.active.saturating_sub(self.allocated)
+    }
+
+    /// Ratio of [`resident`] to [`allocated`] bytes.
+    ///
+    /// Returns `0.0` if nothing is allocated.
+    pub fn resident_ratio(&self) -> f64 {
+        ratio(self.resident, self.allocated)
+    }
+
+    /// Ratio of [`metadata`] to [`allocated`] bytes.
+    ///
+    /// Returns `0.0` if nothing is allocated.
+    pub fn metadata_overhead(&self) -> f64 {
+        ratio(self.metadata, self.allocated)
+    }
+
+    /// Number of bytes in dirty pages, see [`Snapshot::dirty_pages`].
+    pub fn dirty_bytes(&self) -> libc::size_t {
+        self.dirty_pages * self.page_size
+    }
+
+    /// Number of bytes in muzzy pages, see [`Snapshot::muzzy_pages`].
+    pub fn muzzy_bytes(&self) -> libc::size_t {
+        self.muzzy_pages * self.page_size
+    }
+}
+
+/// Snapshot of the statistics of a single arena.
+///
+/// See [`SnapshotMib::arena`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ArenaSnapshot {
+    /// Arena index.
+    pub arena: usize,
+    /// Number of threads currently assigned to the arena
+    /// (`stats.arenas.<i>.nthreads`).
+    pub nthreads: libc::c_uint,
+    /// Number of pages in active extents (`stats.arenas.<i>.pactive`).
+    pub active_pages: libc::size_t,
+    /// Number of pages within unused extents that are potentially dirty
+    /// (`stats.arenas.<i>.pdirty`).
+    pub dirty_pages: libc::size_t,
+    /// Number of pages within unused extents that are muzzy
+    /// (`stats.arenas.<i>.pmuzzy`).
+    pub muzzy_pages: libc::size_t,
+    /// Number of mapped bytes (`stats.arenas.<i>.mapped`).
+    pub mapped: libc::size_t,
+    /// Number of retained bytes (`stats.arenas.<i>.retained`).
+    pub retained: libc::size_t,
+    /// Number of resident bytes (`stats.arenas.<i>.resident`).
+    pub resident: libc::size_t,
+    /// Number of bytes allocated by small objects
+    /// (`stats.arenas.<i>.small.allocated`).
+    pub small_allocated: libc::size_t,
+    /// Cumulative number of small allocation requests served by bins
+    /// (`stats.arenas.<i>.small.nmalloc`).
+    pub small_nmalloc: u64,
+    /// Cumulative number of small objects returned to bins
+    /// (`stats.arenas.<i>.small.ndalloc`).
+    pub small_ndalloc: u64,
+    /// Number of bytes allocated by large objects
+    /// (`stats.arenas.<i>.large.allocated`).
+    pub large_allocated: libc::size_t,
+    /// Cumulative number of large allocation requests
+    /// (`stats.arenas.<i>.large.nmalloc`).
+    pub large_nmalloc: u64,
+    /// Cumulative number of large deallocation requests
+    /// (`stats.arenas.<i>.large.ndalloc`).
+    pub large_ndalloc: u64,
+    /// Number of dirty pages purged (`stats.arenas.<i>.dirty_purged`).
+    pub dirty_purged: u64,
+    /// Number of muzzy pages purged (`stats.arenas.<i>.muzzy_purged`).
+    pub muzzy_purged: u64,
+}
+
+impl ArenaSnapshot {
+    /// Number of bytes allocated by small and large objects.
+    pub fn allocated(&self) -> libc::size_t {
+        self.small_allocated + self.large_allocated
+    }
+}
+
+/// Snapshot of the statistics of a bin size class of a single arena.
+///
+/// See [`SnapshotMib::bin`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BinSnapshot {
+    /// Arena index.
+    pub arena: usize,
+    /// Bin index.
+    pub bin: usize,
+    /// Maximum size supported by the size class (`arenas.bin.<j>.size`).
+    pub size: libc::size_t,
+    /// Number of regions per slab (`arenas.bin.<j>.nregs`).
+    pub nregs: u32,
+    /// Cumulative number of allocations served by the bin
+    /// (`stats.arenas.<i>.bins.<j>.nmalloc`).
+    pub nmalloc: u64,
+    /// Cumulative number of allocations returned to the bin
+    /// (`stats.arenas.<i>.bins.<j>.ndalloc`).
+    pub ndalloc: u64,
+    /// Cumulative number of allocation requests
+    /// (`stats.arenas.<i>.bins.<j>.nrequests`).
+    pub nrequests: u64,
+    /// Current number of regions of the size class
+    /// (`stats.arenas.<i>.bins.<j>.curregs`).
+    pub curregs: libc::size_t,
+    /// Current number of slabs (`stats.arenas.<i>.bins.<j>.curslabs`).
+    pub curslabs: libc::size_t,
+}
+
+impl BinSnapshot {
+    /// Fraction of the regions of the current slabs that are in use.
+    ///
+    /// Returns `1.0` if the bin has no slabs, which waste no memory.
+    pub fn utilization(&self) -> f64 {
+        let regs = self.curslabs * self.nregs as usize;
+        if regs == 0 {
+            1.0
+        } else {
+            self.curregs as f64 / regs as f64
+        }
+    }
+
+    /// Number of bytes in the current slabs that are not used by
+    /// allocations.
+    pub fn fragmented_bytes(&self) -> libc::size_t {
+        (self.curslabs * self.nregs as usize).saturating_sub(self.curregs)
+            * self.size
+    }
+}
+
+/// Management Information Base of all keys read by [`Snapshot`],
+/// [`ArenaSnapshot`], and [`BinSnapshot`].
+///
+/// Translating the keys is done once when the MIB is obtained via
+/// [`Snapshot::mib`], such that taking snapshots only performs MIB lookups.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate jemallocator;
+/// # extern crate jemalloc_ctl;
+/// #
+/// # #[global_allocator]
+/// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+/// #
+/// # fn main() {
+/// use jemalloc_ctl::{arenas, stats};
+/// let mib = stats::Snapshot::mib().unwrap();
+/// let s = mib.take().unwrap();
+/// println!("{} dirty bytes", s.dirty_bytes());
+///
+/// // per-arena and per-bin statistics are read at the epoch of `s`:
+/// for i in 0..arenas::narenas::read().unwrap() as usize {
+///     // arenas that have not been used yet have no statistics:
+///     if let Ok(a) = mib.arena(i) {
+///         println!("arena {}: {} threads", i, a.nthreads);
+///     }
+/// }
+/// let b = mib.bin(0, 0).unwrap();
+/// println!("bin 0 utilization: {:.2}", b.utilization());
+/// # }
+/// ```
+#[derive(Copy, Clone)]
+pub struct SnapshotMib {
+    epoch: ::epoch_mib,
+    allocated: allocated_mib,
+    active: active_mib,
+    metadata: metadata_mib,
+    resident: resident_mib,
+    mapped: mapped_mib,
+    retained: retained_mib,
+    page: ::arenas::page_mib,
+    arena_nthreads: Mib<[usize; 4]>,
+    arena_pactive: Mib<[usize; 4]>,
+    arena_pdirty: Mib<[usize; 4]>,
+    arena_pmuzzy: Mib<[usize; 4]>,
+    arena_mapped: Mib<[usize; 4]>,
+    arena_retained: Mib<[usize; 4]>,
+    arena_resident: Mib<[usize; 4]>,
+    arena_dirty_purged: Mib<[usize; 4]>,
+    arena_muzzy_purged: Mib<[usize; 4]>,
+    arena_small_allocated: Mib<[usize; 5]>,
+    arena_small_nmalloc: Mib<[usize; 5]>,
+    arena_small_ndalloc: Mib<[usize; 5]>,
+    arena_large_allocated: Mib<[usize; 5]>,
+    arena_large_nmalloc: Mib<[usize; 5]>,
+    arena_large_ndalloc: Mib<[usize; 5]>,
+    bin_size: Mib<[usize; 4]>,
+    bin_nregs: Mib<[usize; 4]>,
+    bin_nmalloc: Mib<[usize; 6]>,
+    bin_ndalloc: Mib<[usize; 6]>,
+    bin_nrequests: Mib<[usize; 6]>,
+    bin_curregs: Mib<[usize; 6]>,
+    bin_curslabs: Mib<[usize; 6]>,
+    nbins: libc::c_uint,
+}
+
+impl SnapshotMib {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            epoch: ::epoch::mib()?,
+            allocated: allocated::mib()?,
+            active: active::mib()?,
+            metadata: metadata::mib()?,
+            resident: resident::mib()?,
+            mapped: mapped::mib()?,
+            retained: retained::mib()?,
+            page: ::arenas::page::mib()?,
+            arena_nthreads: b"stats.arenas.0.nthreads\0".name().mib()?,
+            arena_pactive: b"stats.arenas.0.pactive\0".name().mib()?,
+            arena_pdirty: b"stats.arenas.0.pdirty\0".name().mib()?,
+            arena_pmuzzy: b"stats.arenas.0.pmuzzy\0".name().mib()?,
+            arena_mapped: b"stats.arenas.0.mapped\0".name().mib()?,
+            arena_retained: b"stats.arenas.0.retained\0".name().mib()?,
+            arena_resident: b"stats.arenas.0.resident\0".name().mib()?,
+            arena_dirty_purged: b"stats.arenas.0.dirty_purged\0"
+                .name()
+                .mib()?,
+            arena_muzzy_purged: b"stats.arenas.0.muzzy_purged\0"
+                .name()
+                .mib()?,
+            arena_small_allocated: b"stats.arenas.0.small.allocated\0"
+                .name()
+                .mib()?,
+            arena_small_nmalloc: b"stats.arenas.0.small.nmalloc\0"
+                .name()
+                .mib()?,
+            arena_small_ndalloc: b"stats.arenas.0.small.ndalloc\0"
+                .name()
+                .mib()?,
+            arena_large_allocated: b"stats.arenas.0.large.allocated\0"
+                .name()
+                .mib()?,
+            arena_large_nmalloc: b"stats.arenas.0.large.nmalloc\0"
+                .name()
+                .mib()?,
+            arena_large_ndalloc: b"stats.arenas.0.large.ndalloc\0"
+                .name()
+                .mib()?,
+            bin_size: b"arenas.bin.0.size\0".name().mib()?,
+            bin_nregs: b"arenas.bin.0.nregs\0".name().mib()?,
+            bin_nmalloc: b"stats.arenas.0.bins.0.nmalloc\0".name().mib()?,
+            bin_ndalloc: b"stats.arenas.0.bins.0.ndalloc\0".name().mib()?,
+            bin_nrequests: b"stats.arenas.0.bins.0.nrequests\0"
+                .name()
+                .mib()?,
+            bin_curregs: b"stats.arenas.0.bins.0.curregs\0".name().mib()?,
+            bin_curslabs: b"stats.arenas.0.bins.0.curslabs\0".name().mib()?,
+            nbins: ::arenas::nbins::read()?,
+        })
+    }
+
+    /// Advances the epoch and reads the global statistics.
+    pub fn take(&self) -> Result<Snapshot> {
+        let epoch = self.epoch.advance()?;
+        Ok(Snapshot {
+            epoch,
+            allocated: self.allocated.read()?,
+            active: self.active.read()?,
+            metadata: self.metadata.read()?,
+            resident: self.resident.read()?,
+            mapped: self.mapped.read()?,
+            retained: self.retained.read()?,
+            dirty_pages: read_arena(self.arena_pdirty, ARENAS_ALL)?,
+            muzzy_pages: read_arena(self.arena_pmuzzy, ARENAS_ALL)?,
+            page_size: self.page.read()?,
+        })
+    }
+
+    /// Reads the statistics of the arena `arena`.
+    ///
+    /// The epoch is not advanced, so the values correspond to the epoch of
+    /// the last snapshot. Passing [`jemalloc_sys::MALLCTL_ARENAS_ALL`] reads
+    /// the merged statistics of all arenas.
+    ///
+    /// Fails for arenas that are not initialized, e.g., automatic arenas no
+    /// thread has been assigned to yet.
+    pub fn arena(&self, arena: usize) -> Result<ArenaSnapshot> {
+        Ok(ArenaSnapshot {
+            arena,
+            nthreads: read_arena(self.arena_nthreads, arena)?,
+            active_pages: read_arena(self.arena_pactive, arena)?,
+            dirty_pages: read_arena(self.arena_pdirty, arena)?,
+            muzzy_pages: read_arena(self.arena_pmuzzy, arena)?,
+            mapped: read_arena(self.arena_mapped, arena)?,
+            retained: read_arena(self.arena_retained, arena)?,
+            resident: read_arena(self.arena_resident, arena)?,
+            small_allocated: read_arena(self.arena_small_allocated, arena)?,
+            small_nmalloc: read_arena(self.arena_small_nmalloc, arena)?,
+            small_ndalloc: read_arena(self.arena_small_ndalloc, arena)?,
+            large_allocated: read_arena(self.arena_large_allocated, arena)?,
+            large_nmalloc: read_arena(self.arena_large_nmalloc, arena)?,
+            large_ndalloc: read_arena(self.arena_large_ndalloc, arena)?,
+            dirty_purged: read_arena(self.arena_dirty_purged, arena)?,
+            muzzy_purged: read_arena(self.arena_muzzy_purged, arena)?,
+        })
+    }
+
+    /// Reads the statistics of the bin `bin` of the arena `arena`.
+    ///
+    /// The epoch is not advanced, so the values correspond to the epoch of
+    /// the last snapshot. The number of bins is given by
+    /// [`::arenas::nbins`].
+    pub fn bin(&self, arena: usize, bin: usize) -> Result<BinSnapshot> {
+        // `jemalloc` does not reject the index one past the last bin:
+        if bin >= self.nbins as usize {
+            cvt(libc::ENOENT)?;
+        }
+        let mut size = self.bin_size;
+        size[2] = bin;
+        let mut nregs = self.bin_nregs;
+        nregs[2] = bin;
+        Ok(BinSnapshot {
+            arena,
+            bin,
+            size: size.read()?,
+            nregs: nregs.read()?,
+            nmalloc: read_bin(self.bin_nmalloc, arena, bin)?,
+            ndalloc: read_bin(self.bin_ndalloc, arena, bin)?,
+            nrequests: read_bin(self.bin_nrequests, arena, bin)?,
+            curregs: read_bin(self.bin_curregs, arena, bin)?,
+            curslabs: read_bin(self.bin_curslabs, arena, bin)?,
+        })
+    }
+}
+
+const ARENAS_ALL: usize = jemalloc_sys::MALLCTL_ARENAS_ALL;
+
+/// Reads a `stats.arenas.<i>.*` key of the arena `arena`.
+fn read_arena<T, M>(mut mib: Mib<M>, arena: usize) -> Result<T>
+where
+    M: MibArg,
+    Mib<M>: Access<T>,
+{
+    mib[2] = arena;
+    mib.read()
+}
+
+/// Reads a `stats.arenas.<i>.bins.<j>.*` key of the bin `bin` of the arena
+/// `arena`.
+fn read_bin<T>(mut mib: Mib<[usize; 6]>, arena: usize, bin: usize) -> Result<T>
+where
+    Mib<[usize; 6]>: Access<T>,
+{
+    mib[2] = arena;
+    mib[4] = bin;
+    mib.read()
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_precision_loss))]
+fn ratio(a: libc::size_t, b: libc::size_t) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot() {
+        let mib = Snapshot::mib().unwrap();
+        let a = mib.take().unwrap();
+        let b = mib.take().unwrap();
+        assert!(a.epoch < b.epoch);
+        assert!(b.allocated <= b.active);
+        assert!(b.page_size > 0);
+        assert!(b.fragmentation_ratio() >= 1.0);
+    }
+
+    #[test]
+    fn arena_and_bin() {
+        let mib = Snapshot::mib().unwrap();
+        mib.take().unwrap();
+        let all = mib.arena(ARENAS_ALL).unwrap();
+        let arena = mib.arena(0).unwrap();
+        assert!(arena.active_pages <= all.active_pages);
+
+        let nbins = ::arenas::nbins::read().unwrap() as usize;
+        assert!(nbins > 0);
+        let bin = mib.bin(0, 0).unwrap();
+        assert!(bin.size > 0);
+        assert!(bin.nregs > 0);
+        assert!(mib.bin(0, nbins).is_err());
+    }
+
+    #[test]
+    fn bin_utilization() {
+        let bin = BinSnapshot {
+            nregs: 4,
+            curslabs: 2,
+            curregs: 6,
+            ..BinSnapshot::default()
+        };
+        assert_eq!(bin.utilization(), 0.75);
+        // a bin without slabs wastes no memory:
+        assert_eq!(BinSnapshot::default().utilization(), 1.0);
+    }
+}
@@ -1,6 +1,7 @@
 //! Thread specific operations.
 
-use error::Result;
+use error::{cvt, Result};
+use keys::Access;
 use raw::{read, read_mib};
 
 option! {
@@ -113,6 +114,399 @@ impl deallocatedp_mib {
     }
 }
 
+option! {
+    allocated[ str: b"thread.allocated\0", non_str: 2 ] => u64 |
+    ops: r |
+    docs:
+    /// Total number of bytes ever allocated by the current thread.
+    ///
+    /// See [`allocatedp`] for a pointer to the value that avoids the lookup.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// let a = thread::allocated::read().unwrap();
+    /// let buf = vec![0_u8; 1024 * 1024];
+    /// let b = thread::allocated::read().unwrap();
+    /// drop(buf);
+    /// assert!(a + 1024 * 1024 <= b);
+    /// # }
+    /// ```
+    mib_docs: /// See [`allocated`].
+}
+
+option! {
+    deallocated[ str: b"thread.deallocated\0", non_str: 2 ] => u64 |
+    ops: r |
+    docs:
+    /// Total number of bytes ever deallocated by the current thread.
+    ///
+    /// See [`deallocatedp`] for a pointer to the value that avoids the lookup.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// let a = thread::deallocated::read().unwrap();
+    /// drop(vec![0_u8; 1024 * 1024]);
+    /// let b = thread::deallocated::read().unwrap();
+    /// assert!(a + 1024 * 1024 <= b);
+    /// # }
+    /// ```
+    mib_docs: /// See [`deallocated`].
+}
+
+option! {
+    arena[ str: b"thread.arena\0", non_str: 2 ] => libc::c_uint |
+    ops: r, w, u |
+    docs:
+    /// Arena the current thread allocates from.
+    ///
+    /// Writing an arena index binds the current thread to that arena, e.g., one
+    /// created with `arenas.create`, or one of the automatic arenas
+    /// `0..arenas.narenas`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// let arena = thread::arena::read().unwrap();
+    /// println!("allocating from arena {}", arena);
+    /// // bind the thread to arena 0:
+    /// thread::arena::write(0).unwrap();
+    /// assert_eq!(thread::arena::read().unwrap(), 0);
+    /// # }
+    /// ```
+    mib_docs: /// See [`arena`].
+}
+
+option! {
+    tcache_enabled[ str: b"thread.tcache.enabled\0", non_str: 3 ] => bool |
+    ops: r, w, u |
+    docs:
+    /// Whether the thread-specific cache (`tcache`) of the current thread is
+    /// enabled.
+    ///
+    /// Disabling the `tcache` flushes it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// thread::tcache_enabled::write(false).unwrap();
+    /// assert!(!thread::tcache_enabled::read().unwrap());
+    /// thread::tcache_enabled::write(true).unwrap();
+    /// # }
+    /// ```
+    mib_docs: /// See [`tcache_enabled`].
+}
+
+option! {
+    tcache_flush[ str: b"thread.tcache.flush\0", non_str: 3 ] => () |
+    ops: i |
+    docs:
+    /// Flushes the thread-specific cache (`tcache`) of the current thread.
+    ///
+    /// This releases the memory cached by the thread to its arena, e.g., before the
+    /// thread goes idle for a while. The `tcache` is refilled by the allocations of
+    /// the thread.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// thread::tcache_flush::invoke().unwrap();
+    /// # }
+    /// ```
+    mib_docs: /// See [`tcache_flush`].
+}
+
+option! {
+    prof_name[ str: b"thread.prof.name\0", str: 3 ] => &'static str |
+    ops: |
+    docs:
+    /// Name of the current thread in heap profile dumps.
+    ///
+    /// Fails unless `jemalloc` was built with profiling support (cfg
+    /// `jemalloc_prof`) and profiling is enabled with `opt.prof`. Written names
+    /// must be null-terminated.
+    ///
+    /// `jemalloc` frees the previous name when a new one is written, so the
+    /// name is read as a copy, see [`ProfName`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// if let Ok(name) = thread::prof_name::read() {
+    ///     println!("profiled as {}", name);
+    ///     thread::prof_name::write("worker\0").unwrap();
+    /// }
+    /// # }
+    /// ```
+    mib_docs: /// See [`prof_name`].
+}
+
+option! {
+    prof_active[ str: b"thread.prof.active\0", non_str: 3 ] => bool |
+    ops: |
+    docs:
+    /// Whether the allocations of the current thread are sampled for heap profiling.
+    ///
+    /// Fails unless `jemalloc` was built with profiling support (cfg
+    /// `jemalloc_prof`) and profiling is enabled with `opt.prof`. The thread is
+    /// only sampled if `prof.active` is also set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// if let Ok(active) = thread::prof_active::read() {
+    ///     println!("sampled: {}", active);
+    /// }
+    /// # }
+    /// ```
+    mib_docs: /// See [`prof_active`].
+}
+
+#[cfg(jemalloc_5_3)]
+option! {
+    idle[ str: b"thread.idle\0", non_str: 2 ] => () |
+    ops: i |
+    docs:
+    /// Hints that the current thread will be idle for a while.
+    ///
+    /// `jemalloc` flushes the `tcache` of the thread and, if there are more than
+    /// twice as many arenas as CPUs (see [`::opt::narenas`]), purges the unused
+    /// pages of the arena of the thread. Requires `jemalloc` 5.3; with older
+    /// versions use [`tcache_flush`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate jemallocator;
+    /// # extern crate jemalloc_ctl;
+    /// #
+    /// # #[global_allocator]
+    /// # static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+    /// #
+    /// # fn main() {
+    /// use jemalloc_ctl::thread;
+    /// thread::idle::invoke().unwrap();
+    /// # }
+    /// ```
+    mib_docs: /// See [`idle`].
+}
+
+/// Fails with `ENOENT` unless profiling is enabled with `opt.prof`.
+///
+/// `jemalloc` 5.3 does so itself, but older versions crash when the
+/// `thread.prof.*` keys are accessed without `opt.prof`.
+fn prof_enabled() -> Result<()> {
+    if unsafe { read::<bool>(b"opt.prof\0") }? {
+        Ok(())
+    } else {
+        cvt(libc::ENOENT)
+    }
+}
+
+impl prof_name {
+    /// Reads a copy of the value using string API.
+    pub fn read() -> Result<ProfName> {
+        prof_enabled()?;
+        unsafe { read(Self::name().as_bytes()).map(|p| ProfName::copy(p)) }
+    }
+
+    /// Writes `value` using string API.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is not null-terminated.
+    pub fn write(value: &'static str) -> Result<()> {
+        prof_enabled()?;
+        Self::name().write(value)
+    }
+}
+
+impl prof_name_mib {
+    /// Reads a copy of the value using MIB API.
+    pub fn read(self) -> Result<ProfName> {
+        prof_enabled()?;
+        unsafe { read_mib(self.0.as_ref()).map(|p| ProfName::copy(p)) }
+    }
+
+    /// Writes `value` using MIB API.
+    ///
+    /// # Panics
+    ///
+    /// If `value` is not null-terminated.
+    pub fn write(self, value: &'static str) -> Result<()> {
+        prof_enabled()?;
+        self.0.write(value)
+    }
+}
+
+impl prof_active {
+    /// Reads value using string API.
+    pub fn read() -> Result<bool> {
+        prof_enabled()?;
+        Self::name().read()
+    }
+
+    /// Writes `value` using string API.
+    pub fn write(value: bool) -> Result<()> {
+        prof_enabled()?;
+        Self::name().write(value)
+    }
+
+    /// Updates key to `value` returning its old value using string API.
+    pub fn update(value: bool) -> Result<bool> {
+        prof_enabled()?;
+        Self::name().update(value)
+    }
+}
+
+impl prof_active_mib {
+    /// Reads value using MIB API.
+    pub fn read(self) -> Result<bool> {
+        prof_enabled()?;
+        self.0.read()
+    }
+
+    /// Writes `value` using MIB API.
+    pub fn write(self, value: bool) -> Result<()> {
+        prof_enabled()?;
+        self.0.write(value)
+    }
+
+    /// Updates key to `value` returning its old value using MIB API.
+    pub fn update(self, value: bool) -> Result<bool> {
+        prof_enabled()?;
+        self.0.update(value)
+    }
+}
+
+/// Capacity of [`ProfName`] in bytes, including the terminating `\0`.
+///
+/// [`ProfName`]: struct.ProfName.html
+pub const PROF_NAME_LEN: usize = 64;
+
+/// A copy of the name of the current thread in heap profile dumps, see
+/// [`prof_name`].
+///
+/// Dereferences to the name including its terminating `\0`, like the other
+/// strings read by this crate. Longer names are truncated to
+/// [`PROF_NAME_LEN`] bytes.
+///
+/// [`prof_name`]: struct.prof_name.html
+/// [`PROF_NAME_LEN`]: constant.PROF_NAME_LEN.html
+#[derive(Copy, Clone)]
+pub struct ProfName {
+    buf: [u8; PROF_NAME_LEN],
+    len: usize,
+}
+
+impl ProfName {
+    /// Copies the null-terminated name `ptr`.
+    unsafe fn copy(ptr: *const libc::c_char) -> Self {
+        let mut buf = [0; PROF_NAME_LEN];
+        let bytes = if ptr.is_null() {
+            &[]
+        } else {
+            ::slice::from_raw_parts(ptr as *const u8, libc::strlen(ptr))
+        };
+        let bytes = &bytes[..bytes.len().min(PROF_NAME_LEN - 1)];
+        // truncate at a character boundary:
+        let len = match ::str::from_utf8(bytes) {
+            Ok(s) => s.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        buf[..len].copy_from_slice(&bytes[..len]);
+        ProfName { buf, len: len + 1 }
+    }
+
+    /// Returns the name including its terminating `\0`.
+    pub fn as_str(&self) -> &str {
+        // this is safe because `copy` only copies valid UTF-8:
+        unsafe { ::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+
+impl ::ops::Deref for ProfName {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> PartialEq<&'a str> for ProfName {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl ::fmt::Debug for ProfName {
+    fn fmt(&self, f: &mut ::fmt::Formatter) -> ::fmt::Result {
+        ::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl ::fmt::Display for ProfName {
+    fn fmt(&self, f: &mut ::fmt::Formatter) -> ::fmt::Result {
+        ::fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
 /// A thread-local pointer.
 ///
 /// It is neither `Sync` nor `Send`.
@@ -131,3 +525,79 @@ where
         unsafe { *self.0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prof_name_write() {
+        if prof_enabled().is_err() {
+            assert!(prof_name::read().is_err());
+            assert!(prof_name::write("test\0").is_err());
+            return;
+        }
+        prof_name::write("test\0").unwrap();
+        assert_eq!(prof_name::read().unwrap(), "test\0");
+        let mib = prof_name::mib().unwrap();
+        mib.write("mib\0").unwrap();
+        assert_eq!(mib.read().unwrap(), "mib\0");
+    }
+
+    #[test]
+    fn prof_name_outlives_write() {
+        if prof_enabled().is_err() {
+            return;
+        }
+        prof_name::write("first\0").unwrap();
+        let first = prof_name::read().unwrap();
+        // `jemalloc` frees the first name:
+        prof_name::write("second\0").unwrap();
+        assert_eq!(first, "first\0");
+        assert_eq!(prof_name::read().unwrap(), "second\0");
+    }
+
+    #[test]
+    fn prof_active() {
+        if prof_enabled().is_err() {
+            assert!(prof_active::read().is_err());
+            assert!(prof_active::write(false).is_err());
+            return;
+        }
+        let active = prof_active::read().unwrap();
+        assert_eq!(prof_active::update(!active).unwrap(), active);
+        let mib = prof_active::mib().unwrap();
+        assert_eq!(mib.read().unwrap(), !active);
+        mib.write(active).unwrap();
+        assert_eq!(prof_active::read().unwrap(), active);
+    }
+
+    #[test]
+    fn prof_name_truncation() {
+        let mut s = [b'a'; 2 * PROF_NAME_LEN];
+        s[2 * PROF_NAME_LEN - 1] = 0;
+        let name = unsafe { ProfName::copy(s.as_ptr() as *const _) };
+        assert_eq!(name.len(), PROF_NAME_LEN);
+        assert!(name.ends_with('\0'));
+
+        // a multi-byte character is not split:
+        let mut s = [b'a'; PROF_NAME_LEN + 1];
+        s[PROF_NAME_LEN - 2..PROF_NAME_LEN].copy_from_slice("é".as_bytes());
+        s[PROF_NAME_LEN] = 0;
+        let name = unsafe { ProfName::copy(s.as_ptr() as *const _) };
+        assert_eq!(name.len(), PROF_NAME_LEN - 1);
+
+        assert_eq!(unsafe { ProfName::copy(::ptr::null()) }, "\0");
+    }
+
+    #[test]
+    fn arena_binding() {
+        let _lock = ::test_lock();
+        let narenas = ::arenas::narenas::read().unwrap();
+        for a in 0..narenas {
+            arena::write(a).unwrap();
+            assert_eq!(arena::read().unwrap(), a);
+            unsafe { jemalloc_sys::free(jemalloc_sys::malloc(1024)) };
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Parsing of `jemalloc` JSON statistics dumps.
+
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A parsed statistics dump.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dump {
+    /// `jemalloc` version that produced the dump.
+    pub version: String,
+    /// Page size in bytes.
+    pub page: u64,
+    /// Global statistics.
+    pub global: Global,
+    /// Merged statistics of all arenas, if the dump contains them.
+    pub merged: Option<Arena>,
+    /// Statistics of the individual arenas, ordered by arena index.
+    pub arenas: Vec<Arena>,
+}
+
+/// Global statistics, see `stats.*`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Global {
+    pub allocated: u64,
+    pub active: u64,
+    pub metadata: u64,
+    pub resident: u64,
+    pub mapped: u64,
+    pub retained: u64,
+}
+
+/// Allocation counters of the small or large size classes of an arena.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Class {
+    pub allocated: u64,
+    pub nmalloc: u64,
+    pub ndalloc: u64,
+    pub nrequests: u64,
+}
+
+/// Statistics of an arena, see `stats.arenas.<i>.*`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Arena {
+    /// Arena index, `merged`, or `destroyed`.
+    pub name: String,
+    pub nthreads: u64,
+    pub pactive: u64,
+    pub pdirty: u64,
+    pub pmuzzy: u64,
+    pub mapped: u64,
+    pub retained: u64,
+    pub resident: u64,
+    pub dirty_purged: u64,
+    pub muzzy_purged: u64,
+    pub small: Class,
+    pub large: Class,
+    /// Bin statistics, empty if the dump skips them.
+    pub bins: Vec<Bin>,
+}
+
+/// Statistics of a bin of an arena, see `stats.arenas.<i>.bins.<j>.*`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Bin {
+    /// Bin index.
+    pub index: usize,
+    /// Region size in bytes.
+    pub size: u64,
+    /// Number of regions per slab.
+    pub nregs: u64,
+    pub nmalloc: u64,
+    pub ndalloc: u64,
+    pub nrequests: u64,
+    pub curregs: u64,
+    pub curslabs: u64,
+}
+
+impl Bin {
+    /// Bytes of the current slabs that are not used by any region.
+    pub fn fragmented_bytes(&self) -> u64 {
+        (self.curslabs * self.nregs).saturating_sub(self.curregs) * self.size
+    }
+
+    /// Fraction of the regions of the current slabs that are in use.
+    ///
+    /// Returns `1.0` if the bin has no slabs.
+    pub fn utilization(&self) -> f64 {
+        let regs = self.curslabs * self.nregs;
+        if regs == 0 {
+            1.0
+        } else {
+            self.curregs as f64 / regs as f64
+        }
+    }
+}
+
+impl Dump {
+    /// Parses the JSON output of `malloc_stats_print`.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let v: Value = serde_json::from_str(json)
+            .map_err(|e| format!("invalid JSON: {}", e))?;
+        Self::from_value(&v)
+    }
+
+    /// Reads a dump from a JSON value.
+    pub fn from_value(v: &Value) -> Result<Self, String> {
+        let je = field(v, "jemalloc")?;
+        let version = match je.get("version").and_then(Value::as_str) {
+            Some(v) => v.to_string(),
+            None => "unknown".to_string(),
+        };
+        let page = je
+            .pointer("/arenas/page")
+            .and_then(Value::as_u64)
+            .unwrap_or(4096);
+        let sizes = match je.pointer("/arenas/bin").and_then(Value::as_array) {
+            Some(bins) => bins
+                .iter()
+                .map(|b| Ok((u64_field(b, "size")?, u64_field(b, "nregs")?)))
+                .collect::<Result<Vec<_>, String>>()?,
+            None => Vec::new(),
+        };
+
+        let stats = field(je, "stats").map_err(|_| {
+            "the dump contains no statistics; was jemalloc built with \
+             `--enable-stats`?"
+                .to_string()
+        })?;
+        let global = Global {
+            allocated: u64_field(stats, "allocated")?,
+            active: u64_field(stats, "active")?,
+            metadata: u64_field(stats, "metadata")?,
+            resident: u64_field(stats, "resident")?,
+            mapped: u64_field(stats, "mapped")?,
+            retained: u64_field(stats, "retained")?,
+        };
+
+        let mut merged = None;
+        let mut arenas = Vec::new();
+        if let Some(a) = je.get("stats.arenas").and_then(Value::as_object) {
+            for (name, v) in a {
+                let arena = arena(name, v, &sizes)
+                    .map_err(|e| format!("arena {}: {}", name, e))?;
+                if name == "merged" {
+                    merged = Some(arena);
+                } else {
+                    arenas.push(arena);
+                }
+            }
+        }
+        arenas.sort_by(|a, b| arena_order(&a.name, &b.name));
+
+        Ok(Self {
+            version,
+            page,
+            global,
+            merged,
+            arenas,
+        })
+    }
+}
+
+/// Orders arenas by index, followed by `destroyed`.
+fn arena_order(a: &str, b: &str) -> Ordering {
+    match (a.parse::<usize>(), b.parse::<usize>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+fn arena(
+    name: &str,
+    v: &Value,
+    sizes: &[(u64, u64)],
+) -> Result<Arena, String> {
+    let class = |name| -> Result<Class, String> {
+        let c = field(v, name)?;
+        Ok(Class {
+            allocated: u64_field(c, "allocated")?,
+            nmalloc: u64_field(c, "nmalloc")?,
+            ndalloc: u64_field(c, "ndalloc")?,
+            nrequests: u64_field(c, "nrequests")?,
+        })
+    };
+    let bins = match v.get("bins").and_then(Value::as_array) {
+        Some(bins) => bins
+            .iter()
+            .enumerate()
+            .map(|(index, b)| {
+                let (size, nregs) =
+                    sizes.get(index).cloned().unwrap_or((0, 0));
+                Ok(Bin {
+                    index,
+                    size,
+                    nregs,
+                    nmalloc: u64_field(b, "nmalloc")?,
+                    ndalloc: u64_field(b, "ndalloc")?,
+                    nrequests: u64_field(b, "nrequests")?,
+                    curregs: u64_field(b, "curregs")?,
+                    curslabs: u64_field(b, "curslabs")?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?,
+        None => Vec::new(),
+    };
+    Ok(Arena {
+        name: name.to_string(),
+        nthreads: u64_field(v, "nthreads")?,
+        pactive: u64_field(v, "pactive")?,
+        pdirty: u64_field(v, "pdirty")?,
+        pmuzzy: u64_field(v, "pmuzzy")?,
+        mapped: u64_field(v, "mapped")?,
+        retained: u64_field(v, "retained")?,
+        resident: u64_field(v, "resident")?,
+        dirty_purged: u64_field(v, "dirty_purged")?,
+        muzzy_purged: u64_field(v, "muzzy_purged")?,
+        small: class("small")?,
+        large: class("large")?,
+        bins,
+    })
+}
+
+fn field<'a>(v: &'a Value, name: &str) -> Result<&'a Value, String> {
+    v.get(name).ok_or_else(|| format!("missing `{}`", name))
+}
+
+fn u64_field(v: &Value, name: &str) -> Result<u64, String> {
+    field(v, name)?
+        .as_u64()
+        .ok_or_else(|| format!("`{}` is not an unsigned integer", name))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A trimmed down dump in the format of `jemalloc` 5.1.
+    pub const DUMP: &str = r#"{
+	"jemalloc": {
+		"version": "5.1.0-0-g61efbda7098de6fe64c362d309824864308c36d4",
+		"arenas": {
+			"narenas": 2,
+			"page": 4096,
+			"bin": [
+				{"size": 8, "nregs": 512, "slab_size": 4096},
+				{"size": 16, "nregs": 256, "slab_size": 4096}
+			]
+		},
+		"stats": {
+			"allocated": 1048576,
+			"active": 1310720,
+			"metadata": 262144,
+			"metadata_thp": 0,
+			"resident": 2097152,
+			"mapped": 4194304,
+			"retained": 1048576
+		},
+		"stats.arenas": {
+			"merged": {
+				"nthreads": 3,
+				"pactive": 320,
+				"pdirty": 10,
+				"pmuzzy": 2,
+				"dirty_purged": 100,
+				"muzzy_purged": 20,
+				"mapped": 4194304,
+				"retained": 1048576,
+				"resident": 2097152,
+				"small": {"allocated": 24576, "nmalloc": 2000, "ndalloc": 1000, "nrequests": 4000},
+				"large": {"allocated": 1024000, "nmalloc": 10, "ndalloc": 5, "nrequests": 10},
+				"bins": [
+					{"nmalloc": 1500, "ndalloc": 1000, "nrequests": 3000, "curregs": 500, "curslabs": 2},
+					{"nmalloc": 512, "ndalloc": 0, "nrequests": 1000, "curregs": 512, "curslabs": 2}
+				]
+			},
+			"10": {
+				"nthreads": 1,
+				"pactive": 20,
+				"pdirty": 0,
+				"pmuzzy": 0,
+				"dirty_purged": 0,
+				"muzzy_purged": 0,
+				"mapped": 2097152,
+				"retained": 0,
+				"resident": 1048576,
+				"small": {"allocated": 8192, "nmalloc": 500, "ndalloc": 0, "nrequests": 1000},
+				"large": {"allocated": 0, "nmalloc": 0, "ndalloc": 0, "nrequests": 0}
+			},
+			"2": {
+				"nthreads": 2,
+				"pactive": 300,
+				"pdirty": 10,
+				"pmuzzy": 2,
+				"dirty_purged": 100,
+				"muzzy_purged": 20,
+				"mapped": 2097152,
+				"retained": 1048576,
+				"resident": 1048576,
+				"small": {"allocated": 16384, "nmalloc": 1500, "ndalloc": 1000, "nrequests": 3000},
+				"large": {"allocated": 1024000, "nmalloc": 10, "ndalloc": 5, "nrequests": 10}
+			}
+		}
+	}
+}"#;
+
+    #[test]
+    fn parse() {
+        let d = Dump::parse(DUMP).unwrap();
+        assert!(d.version.starts_with("5.1.0"));
+        assert_eq!(d.page, 4096);
+        assert_eq!(d.global.allocated, 1_048_576);
+        assert_eq!(d.global.retained, 1_048_576);
+
+        let m = d.merged.unwrap();
+        assert_eq!(m.nthreads, 3);
+        assert_eq!(m.small.nmalloc, 2000);
+        assert_eq!(m.large.allocated, 1_024_000);
+        assert_eq!(m.bins.len(), 2);
+        assert_eq!(m.bins[1].index, 1);
+        assert_eq!(m.bins[1].size, 16);
+        assert_eq!(m.bins[1].nregs, 256);
+
+        let names: Vec<_> = d.arenas.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["2", "10"]);
+        assert!(d.arenas[0].bins.is_empty());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Dump::parse("{").is_err());
+        assert!(Dump::parse("{}").unwrap_err().contains("jemalloc"));
+        let no_stats = r#"{"jemalloc": {"version": "5.1.0"}}"#;
+        assert!(Dump::parse(no_stats).unwrap_err().contains("enable-stats"));
+    }
+
+    #[test]
+    fn bin_fragmentation() {
+        let d = Dump::parse(DUMP).unwrap();
+        let bins = d.merged.unwrap().bins;
+        // 2 slabs of 512 8-byte regions, 500 in use:
+        assert_eq!(bins[0].fragmented_bytes(), (1024 - 500) * 8);
+        assert!((bins[0].utilization() - 500.0 / 1024.0).abs() < 1e-9);
+        assert_eq!(Bin::default().fragmented_bytes(), 0);
+        assert_eq!(Bin::default().utilization(), 1.0);
+    }
+}
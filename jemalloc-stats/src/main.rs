@@ -0,0 +1,231 @@
+//! `jemalloc-stats` renders `jemalloc` JSON statistics dumps as tables.
+//!
+//! See the `README.md` or `jemalloc-stats --help` for usage.
+
+extern crate jemallocator;
+extern crate serde_json;
+
+#[global_allocator]
+static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+mod dump;
+mod render;
+mod run;
+
+use dump::Dump;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+const USAGE: &str = "\
+Renders jemalloc JSON statistics dumps as tables.
+
+USAGE:
+    jemalloc-stats show [--arenas] <DUMP>
+    jemalloc-stats diff <BEFORE> <AFTER>
+    jemalloc-stats top [-n <N>] <DUMP>
+    jemalloc-stats run [--lib <LIB>] [-o <FILE>] [--] <PROGRAM> [ARGS...]
+
+COMMANDS:
+    show    Shows the global and arena statistics of a dump
+    diff    Shows the changes between two dumps
+    top     Shows the N (default: 10) bins with the most fragmented bytes
+    run     Runs PROGRAM with LIB (default: libjemalloc.so.2) preloaded and
+            shows the statistics printed when it exits; -o saves them to FILE
+
+A DUMP of `-` is read from the standard input.
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli(&args) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("jemalloc-stats: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Runs the command in `args` and returns the exit code.
+fn cli(args: &[String]) -> Result<i32, String> {
+    let (command, args) = match args.split_first() {
+        Some((c, args)) => (c.as_str(), args),
+        None => return Err(format!("missing command\n\n{}", USAGE)),
+    };
+    let out = match command {
+        "show" => {
+            let opts = parse(args, &["--arenas"], &[], false)?;
+            render::show(&load(single(&opts.args)?)?, opts.flag("--arenas"))
+        }
+        "diff" => match args {
+            [before, after] => render::diff(&load(before)?, &load(after)?),
+            _ => return Err("`diff` expects two dumps".to_string()),
+        },
+        "top" => {
+            let opts = parse(args, &[], &["-n"], false)?;
+            let n = match opts.value("-n") {
+                Some(n) => n
+                    .parse()
+                    .map_err(|_| format!("invalid number of bins `{}`", n))?,
+                None => 10,
+            };
+            render::top(&load(single(&opts.args)?)?, n)?
+        }
+        "run" => return run(args),
+        "-h" | "--help" | "help" => USAGE.to_string(),
+        c => return Err(format!("unknown command `{}`\n\n{}", c, USAGE)),
+    };
+    print(&out);
+    Ok(0)
+}
+
+fn run(args: &[String]) -> Result<i32, String> {
+    // The options following the program are its own:
+    let opts = parse(args, &[], &["--lib", "-o"], true)?;
+    let (program, args) = opts
+        .args
+        .split_first()
+        .ok_or_else(|| "`run` expects a program".to_string())?;
+
+    let lib = opts.value("--lib").unwrap_or(run::DEFAULT_LIB);
+    let out = run::run(lib, program, args)?;
+    if let Some(path) = opts.value("-o") {
+        fs::write(path, &out.json)
+            .map_err(|e| format!("failed to write `{}`: {}", path, e))?;
+    }
+    print(&render::show(&Dump::from_value(&out.value)?, false));
+    // Exit with the status of the program, or 1 if it was killed:
+    Ok(out.status.code().unwrap_or(1))
+}
+
+/// The options and other arguments of a command.
+#[derive(Debug, Default, PartialEq)]
+struct Options<'a> {
+    /// The flags present, e.g., `--arenas`.
+    flags: Vec<&'a str>,
+    /// The options with a value, e.g., `-n 5`, in order.
+    values: Vec<(&'a str, &'a str)>,
+    /// The other arguments, in order.
+    args: Vec<&'a str>,
+}
+
+impl<'a> Options<'a> {
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(&name)
+    }
+
+    /// Returns the last value of the option `name`.
+    fn value(&self, name: &str) -> Option<&'a str> {
+        self.values.iter().rev().find(|v| v.0 == name).map(|v| v.1)
+    }
+}
+
+/// Parses the `flags` and the options with a value in `values` from `args`,
+/// in any order.
+///
+/// A `--` ends the options. With `stop`, so does the first other argument,
+/// e.g., a program whose own options follow it.
+fn parse<'a>(
+    args: &'a [String],
+    flags: &[&str],
+    values: &[&str],
+    stop: bool,
+) -> Result<Options<'a>, String> {
+    let mut opts = Options::default();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(a) = args.next() {
+        if a == "--" {
+            break;
+        } else if flags.contains(&a) {
+            opts.flags.push(a);
+        } else if values.contains(&a) {
+            let v = args
+                .next()
+                .ok_or_else(|| format!("`{}` expects a value", a))?;
+            opts.values.push((a, v));
+        } else if a.starts_with('-') && a != "-" {
+            return Err(format!("unknown option `{}`", a));
+        } else {
+            opts.args.push(a);
+            if stop {
+                break;
+            }
+        }
+    }
+    opts.args.extend(args);
+    Ok(opts)
+}
+
+fn single<'a>(args: &[&'a str]) -> Result<&'a str, String> {
+    match *args {
+        [path] => Ok(path),
+        [] => Err("missing dump".to_string()),
+        _ => Err(format!("unexpected arguments: {}", args[1..].join(" "))),
+    }
+}
+
+/// Loads the dump at `path`, or from the standard input if `path` is `-`.
+fn load(path: &str) -> Result<Dump, String> {
+    let mut json = String::new();
+    let read = if path == "-" {
+        io::stdin().read_to_string(&mut json)
+    } else {
+        fs::File::open(path).and_then(|mut f| f.read_to_string(&mut json))
+    };
+    read.map_err(|e| format!("failed to read `{}`: {}", path, e))?;
+    Dump::parse(&json).map_err(|e| format!("`{}`: {}", path, e))
+}
+
+fn print(s: &str) {
+    // Ignore errors writing to a closed pipe, e.g., `| head`:
+    let _ = io::stdout().write_all(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let a = args("--lib foo.so -o out.json -- prog -o x");
+        let opts = parse(&a, &[], &["--lib", "-o"], true).unwrap();
+        assert_eq!(opts.value("--lib"), Some("foo.so"));
+        assert_eq!(opts.value("-o"), Some("out.json"));
+        assert_eq!(opts.args, ["prog", "-o", "x"]);
+
+        // In any order, and up to the program without `--`:
+        let a = args("-o out.json --lib foo.so prog --lib bar.so");
+        let opts = parse(&a, &[], &["--lib", "-o"], true).unwrap();
+        assert_eq!(opts.value("--lib"), Some("foo.so"));
+        assert_eq!(opts.value("-o"), Some("out.json"));
+        assert_eq!(opts.args, ["prog", "--lib", "bar.so"]);
+
+        // Before or after the dump:
+        for a in &[args("-n 5 dump.json"), args("dump.json -n 5")] {
+            let opts = parse(a, &[], &["-n"], false).unwrap();
+            assert_eq!(opts.value("-n"), Some("5"));
+            assert_eq!(single(&opts.args).unwrap(), "dump.json");
+        }
+        assert!(parse(&args("dump.json -n"), &[], &["-n"], false).is_err());
+        assert!(parse(&args("-x dump.json"), &[], &["-n"], false).is_err());
+
+        let a = args("- --arenas");
+        let opts = parse(&a, &["--arenas"], &[], false).unwrap();
+        assert!(opts.flag("--arenas"));
+        assert_eq!(single(&opts.args).unwrap(), "-");
+        assert!(single(&["a", "b"]).is_err());
+        assert!(single(&[]).is_err());
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert!(cli(&[]).is_err());
+        assert!(cli(&args("frobnicate")).unwrap_err().contains("unknown"));
+        assert!(cli(&args("diff a.json")).is_err());
+    }
+}
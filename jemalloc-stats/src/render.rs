@@ -0,0 +1,299 @@
+//! Rendering of statistics dumps as text tables.
+
+use dump::{Arena, Bin, Dump};
+use std::fmt::Write;
+
+/// A table with a left-aligned first column and right-aligned other columns.
+struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(header: &[&str]) -> Self {
+        Self {
+            rows: vec![header.iter().map(|h| h.to_string()).collect()],
+        }
+    }
+
+    fn row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    fn render(&self, out: &mut String) {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                self.rows
+                    .iter()
+                    .filter_map(|r| r.get(c))
+                    .map(|s| s.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        for row in &self.rows {
+            let mut line = String::new();
+            for (c, cell) in row.iter().enumerate() {
+                if c == 0 {
+                    let _ = write!(line, "{:<w$}", cell, w = widths[c]);
+                } else {
+                    let _ = write!(line, "  {:>w$}", cell, w = widths[c]);
+                }
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+    }
+}
+
+/// Formats a number of bytes using binary units, e.g., `1.5 MiB`.
+pub fn bytes(n: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = n as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", n)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn signed(before: u64, after: u64, f: fn(u64) -> String) -> String {
+    if after >= before {
+        format!("+{}", f(after - before))
+    } else {
+        format!("-{}", f(before - after))
+    }
+}
+
+fn count(n: u64) -> String {
+    n.to_string()
+}
+
+fn percent(ratio: f64) -> String {
+    format!("{:.1}%", ratio * 100.0)
+}
+
+fn arena_row(a: &Arena, page: u64) -> Vec<String> {
+    vec![
+        a.name.clone(),
+        count(a.nthreads),
+        bytes(a.pactive * page),
+        bytes(a.pdirty * page),
+        bytes(a.pmuzzy * page),
+        bytes(a.resident),
+        bytes(a.small.allocated),
+        bytes(a.large.allocated),
+        count(a.small.nmalloc + a.large.nmalloc),
+        count(a.small.ndalloc + a.large.ndalloc),
+    ]
+}
+
+/// Renders the global statistics and the arena statistics of `d`.
+///
+/// The statistics of the individual arenas are only rendered if `arenas` is
+/// set.
+pub fn show(d: &Dump, arenas: bool) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "jemalloc {}\n", d.version);
+
+    let g = &d.global;
+    let mut t = Table::new(&["statistic", "value"]);
+    for &(name, value) in &[
+        ("allocated", g.allocated),
+        ("active", g.active),
+        ("metadata", g.metadata),
+        ("resident", g.resident),
+        ("mapped", g.mapped),
+        ("retained", g.retained),
+    ] {
+        t.row(vec![name.to_string(), bytes(value)]);
+    }
+    t.render(&mut out);
+
+    let mut t = Table::new(&[
+        "arena", "threads", "active", "dirty", "muzzy", "resident", "small",
+        "large", "allocs", "deallocs",
+    ]);
+    if let Some(ref m) = d.merged {
+        t.row(arena_row(m, d.page));
+    }
+    if arenas {
+        for a in &d.arenas {
+            t.row(arena_row(a, d.page));
+        }
+    }
+    if t.rows.len() > 1 {
+        out.push('\n');
+        t.render(&mut out);
+    }
+    out
+}
+
+/// Renders the changes from `before` to `after`.
+pub fn diff(before: &Dump, after: &Dump) -> String {
+    let mut out = String::new();
+    if before.version != after.version {
+        let _ = writeln!(
+            out,
+            "warning: comparing jemalloc {} with jemalloc {}\n",
+            before.version, after.version
+        );
+    }
+
+    let (b, a) = (&before.global, &after.global);
+    let mut rows = vec![
+        (
+            "allocated",
+            b.allocated,
+            a.allocated,
+            bytes as fn(u64) -> String,
+        ),
+        ("active", b.active, a.active, bytes),
+        ("metadata", b.metadata, a.metadata, bytes),
+        ("resident", b.resident, a.resident, bytes),
+        ("mapped", b.mapped, a.mapped, bytes),
+        ("retained", b.retained, a.retained, bytes),
+    ];
+    if let (Some(b), Some(a)) = (&before.merged, &after.merged) {
+        rows.extend_from_slice(&[
+            (
+                "threads",
+                b.nthreads,
+                a.nthreads,
+                count as fn(u64) -> String,
+            ),
+            (
+                "allocs",
+                b.small.nmalloc + b.large.nmalloc,
+                a.small.nmalloc + a.large.nmalloc,
+                count,
+            ),
+            (
+                "deallocs",
+                b.small.ndalloc + b.large.ndalloc,
+                a.small.ndalloc + a.large.ndalloc,
+                count,
+            ),
+            (
+                "purged pages",
+                b.dirty_purged + b.muzzy_purged,
+                a.dirty_purged + a.muzzy_purged,
+                count,
+            ),
+        ]);
+    }
+
+    let mut t = Table::new(&["statistic", "before", "after", "change"]);
+    for (name, b, a, f) in rows {
+        t.row(vec![name.to_string(), f(b), f(a), signed(b, a, f)]);
+    }
+    t.render(&mut out);
+    out
+}
+
+/// Renders the `n` bins of the merged arena statistics with the most bytes
+/// in partially used slabs.
+pub fn top(d: &Dump, n: usize) -> Result<String, String> {
+    let merged = d.merged.as_ref().ok_or_else(|| {
+        "the dump contains no merged arena statistics".to_string()
+    })?;
+    if merged.bins.is_empty() {
+        return Err("the dump contains no bin statistics".to_string());
+    }
+    let mut bins: Vec<&Bin> = merged
+        .bins
+        .iter()
+        .filter(|b| b.fragmented_bytes() > 0)
+        .collect();
+    bins.sort_by(|a, b| {
+        b.fragmented_bytes()
+            .cmp(&a.fragmented_bytes())
+            .then(a.index.cmp(&b.index))
+    });
+    bins.truncate(n);
+
+    let mut out = String::new();
+    let mut t = Table::new(&[
+        "bin",
+        "size",
+        "regions",
+        "slabs",
+        "utilization",
+        "fragmented",
+    ]);
+    for b in bins {
+        t.row(vec![
+            count(b.index as u64),
+            bytes(b.size),
+            count(b.curregs),
+            count(b.curslabs),
+            percent(b.utilization()),
+            bytes(b.fragmented_bytes()),
+        ]);
+    }
+    t.render(&mut out);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dump::tests::DUMP;
+
+    #[test]
+    fn byte_units() {
+        assert_eq!(bytes(0), "0 B");
+        assert_eq!(bytes(1023), "1023 B");
+        assert_eq!(bytes(1536), "1.5 KiB");
+        assert_eq!(bytes(3 << 30), "3.0 GiB");
+        assert_eq!(signed(10, 4, count), "-6");
+        assert_eq!(signed(4, 10, count), "+6");
+    }
+
+    #[test]
+    fn show_arenas() {
+        let d = Dump::parse(DUMP).unwrap();
+        let s = show(&d, false);
+        assert!(s.starts_with("jemalloc 5.1.0"));
+        assert!(s
+            .lines()
+            .any(|l| l.starts_with("allocated ") && l.ends_with(" 1.0 MiB")));
+        assert!(s.contains("\nmerged"));
+        assert!(!s.contains("\n10 "));
+        assert!(show(&d, true).contains("\n10 "));
+    }
+
+    #[test]
+    fn diff_dumps() {
+        let before = Dump::parse(DUMP).unwrap();
+        let mut after = before.clone();
+        after.global.allocated += 1024;
+        after.merged.as_mut().unwrap().small.nmalloc += 5;
+        let s = diff(&before, &after);
+        assert!(s.contains("+1.0 KiB"));
+        assert!(s.contains("+5"));
+        assert!(s.contains("+0 B"));
+    }
+
+    #[test]
+    fn top_bins() {
+        let d = Dump::parse(DUMP).unwrap();
+        let s = top(&d, 10).unwrap();
+        let lines: Vec<_> = s.lines().collect();
+        // Bin 1 is full, so only bin 0 is listed:
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("0 "));
+        assert!(lines[1].ends_with("4.1 KiB"));
+        assert!(lines[1].contains("48.8%"));
+        assert_eq!(top(&d, 0).unwrap().lines().count(), 1);
+
+        let mut d = d;
+        d.merged = None;
+        assert!(top(&d, 10).is_err());
+    }
+}
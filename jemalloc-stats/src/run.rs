@@ -0,0 +1,176 @@
+//! Running a program with a preloaded `jemalloc` and capturing its statistics.
+
+use serde_json::{self, Value};
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Library preloaded when none is specified.
+pub const DEFAULT_LIB: &str = "libjemalloc.so.2";
+
+/// Options making `jemalloc` print JSON statistics when the program exits.
+const MALLOC_CONF: &str = "stats_print:true,stats_print_opts:J";
+
+/// The statistics printed by a program.
+pub struct Output {
+    /// Exit status of the program.
+    pub status: ExitStatus,
+    /// The JSON statistics dump.
+    pub json: String,
+    /// The parsed dump.
+    pub value: Value,
+}
+
+/// Runs `program` with `lib` preloaded.
+///
+/// The statistics are printed by `jemalloc` to the standard error of the
+/// program; the rest of the standard error is forwarded to ours as the
+/// program writes it.
+pub fn run(lib: &str, program: &str, args: &[&str]) -> Result<Output, String> {
+    let conf = match env::var("MALLOC_CONF") {
+        Ok(ref c) if !c.is_empty() => format!("{},{}", c, MALLOC_CONF),
+        _ => MALLOC_CONF.to_string(),
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .env("LD_PRELOAD", lib)
+        .env("MALLOC_CONF", conf)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run `{}`: {}", program, e))?;
+
+    let mut splitter = Splitter::default();
+    let read = forward(
+        child.stderr.take().expect("stderr is piped"),
+        &mut splitter,
+        &mut io::stderr(),
+    );
+    let status = child
+        .wait()
+        .map_err(|e| format!("failed to wait for `{}`: {}", program, e))?;
+    read.map_err(|e| format!("failed to read the standard error: {}", e))?;
+
+    match splitter.finish(&mut io::stderr()) {
+        Some((json, value)) => Ok(Output {
+            status,
+            json,
+            value,
+        }),
+        None => Err(format!(
+            "`{}` did not print jemalloc statistics; is `{}` an \
+             unprefixed jemalloc built with `--enable-stats`?",
+            program, lib
+        )),
+    }
+}
+
+/// Feeds `input` to `splitter` line by line until its end.
+fn forward<R: io::Read, W: Write>(
+    input: R,
+    splitter: &mut Splitter,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        splitter.push(&String::from_utf8_lossy(&line), out);
+    }
+}
+
+/// Separates the JSON statistics dumps from the rest of a standard error
+/// read line by line.
+///
+/// A dump starts with a `{` line followed by a `"jemalloc"` line, and ends
+/// with a `}` line. Only the lines of a possible dump are held back.
+#[derive(Default)]
+struct Splitter {
+    /// The lines of a possible dump.
+    pending: String,
+    /// The number of lines in `pending`.
+    lines: usize,
+    /// The last dump and its parsed value.
+    dump: Option<(String, Value)>,
+}
+
+impl Splitter {
+    /// Writes `line` to `out` unless it may belong to a dump.
+    ///
+    /// Errors writing to `out` are ignored.
+    fn push<W: Write>(&mut self, line: &str, out: &mut W) {
+        if self.lines == 0 {
+            if line.trim_end() == "{" {
+                self.pending.push_str(line);
+                self.lines = 1;
+            } else {
+                let _ = out.write_all(line.as_bytes());
+            }
+            return;
+        }
+        if self.lines == 1 && !line.trim_start().starts_with("\"jemalloc\"") {
+            // Not a dump, but `line` may start one:
+            self.flush(out);
+            return self.push(line, out);
+        }
+        self.pending.push_str(line);
+        self.lines += 1;
+        if line.starts_with('}') {
+            if let Ok(value) = serde_json::from_str(&self.pending) {
+                let json = mem::take(&mut self.pending);
+                let json = json.trim_end().to_string();
+                self.lines = 0;
+                self.dump = Some((json, value));
+            }
+        }
+    }
+
+    /// Writes the held back lines to `out`, and returns the last dump.
+    fn finish<W: Write>(mut self, out: &mut W) -> Option<(String, Value)> {
+        self.flush(out);
+        self.dump
+    }
+
+    fn flush<W: Write>(&mut self, out: &mut W) {
+        let _ = out.write_all(self.pending.as_bytes());
+        self.pending.clear();
+        self.lines = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dump::tests::DUMP;
+    use dump::Dump;
+
+    #[test]
+    fn split_dump() {
+        let stderr = format!("warning: foo\n{{\n{}\n{{not json\n}}", DUMP);
+        let mut splitter = Splitter::default();
+        let mut out = Vec::new();
+        forward(stderr.as_bytes(), &mut splitter, &mut out).unwrap();
+        let (json, value) = splitter.finish(&mut out).unwrap();
+        assert_eq!(json, DUMP);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "warning: foo\n{\n{not json\n}"
+        );
+        assert_eq!(
+            Dump::from_value(&value).unwrap(),
+            Dump::parse(DUMP).unwrap()
+        );
+
+        // The lines of an incomplete dump are forwarded at the end:
+        let mut splitter = Splitter::default();
+        let mut out = Vec::new();
+        splitter.push("{\n", &mut out);
+        splitter.push("\"jemalloc\": {\n", &mut out);
+        assert!(out.is_empty());
+        assert!(splitter.finish(&mut out).is_none());
+        assert_eq!(out, b"{\n\"jemalloc\": {\n");
+    }
+}
@@ -9,8 +9,12 @@
 // except according to those terms.
 
 extern crate cc;
+#[cfg(feature = "verify_bindings")]
+extern crate ctest2;
 extern crate fs_extra;
+extern crate pkg_config;
 
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::fs::File;
@@ -44,6 +48,107 @@ const NO_BG_THREAD_TARGETS: &[&str] = &["musl"];
 // https://github.com/rust-lang/rust/commit/ed015456a114ae907a36af80c06f81ea93182a24
 const NO_UNPREFIXED_MALLOC: &[&str] = &["android", "dragonfly", "musl", "darwin"];
 
+// Cargo features mapped to the `configure` options they pass:
+const FEATURE_OPTIONS: &[(&str, &str)] = &[
+    ("DEBUG", "--enable-debug"),
+    ("PROFILING", "--enable-prof"),
+    ("PROFILING_LIBUNWIND", "--enable-prof-libunwind"),
+    ("DISABLE_PROFILING_LIBGCC", "--disable-prof-libgcc"),
+    ("DISABLE_PROFILING_GCC", "--disable-prof-gcc"),
+    ("STATS", "--enable-stats"),
+    ("DISABLE_FILL", "--disable-fill"),
+    ("UTRACE", "--enable-utrace"),
+    ("XMALLOC", "--enable-xmalloc"),
+    ("DISABLE_CACHE_OBLIVIOUS", "--disable-cache-oblivious"),
+    ("LAZY_LOCK", "--enable-lazy-lock"),
+    ("DISABLE_ZONE_ALLOCATOR", "--disable-zone-allocator"),
+    ("DISABLE_INITIAL_EXEC_TLS", "--disable-initial-exec-tls"),
+];
+
+type Validate = fn(&str) -> Result<(), String>;
+
+// `configure` options taking values, passed via the `JEMALLOC_SYS_{OPTION}`
+// environment variables, and the validation of their values:
+const VALUE_OPTIONS: &[(&str, Validate)] = &[
+    ("--with-lg-page", |v| lg(v, 12, 16)),
+    ("--with-lg-hugepage", |v| lg(v, 12, 30)),
+    ("--with-lg-page-sizes", |v| {
+        v.split(',').try_for_each(|v| lg(v, 12, 30))
+    }),
+    ("--with-lg-quantum", |v| lg(v, 3, 8)),
+    ("--with-lg-vaddr", |v| lg(v, 16, 64)),
+    ("--with-static-libunwind", |v| {
+        if Path::new(v).exists() {
+            Ok(())
+        } else {
+            Err("the file does not exist".to_string())
+        }
+    }),
+];
+
+// Symbol prefix used unless `JEMALLOC_SYS_PREFIX` is set or unprefixed
+// symbols are requested:
+const DEFAULT_PREFIX: &str = "_rjem_";
+
+// System `jemalloc` versions compatible with the bindings, see
+// `JEMALLOC_SYS_USE_PKG_CONFIG`:
+const SYSTEM_MIN_VERSION: &str = "5.0.0";
+const SYSTEM_MAJOR_VERSION: &str = "5";
+
+// `jemalloc` features exposed as `build_info` constants and `jemalloc_{name}`
+// cfgs, and the macros of `jemalloc_internal_defs.h` enabling them:
+const HEADER_FEATURES: &[(&str, &str)] = &[
+    ("prof", "JEMALLOC_PROF"),
+    ("stats", "JEMALLOC_STATS"),
+    ("debug", "JEMALLOC_DEBUG"),
+    ("fill", "JEMALLOC_FILL"),
+    ("utrace", "JEMALLOC_UTRACE"),
+    ("xmalloc", "JEMALLOC_XMALLOC"),
+    ("lazy_lock", "JEMALLOC_LAZY_LOCK"),
+    ("cache_oblivious", "JEMALLOC_CACHE_OBLIVIOUS"),
+    ("background_thread", "JEMALLOC_BACKGROUND_THREAD"),
+];
+
+// Pre-generated `configure` outputs for the `cc` build backend, one directory
+// per target, see `JEMALLOC_SYS_BUILD_BACKEND`:
+const PREGENERATED_CONFIG_DIR: &str = "configure/targets";
+
+// Bundled `jemalloc` releases, newest first: the cargo feature selecting the
+// release, its version, its source directory, and the directory of its
+// pre-generated `configure` script and `VERSION` file. The last release is the
+// default.
+const BUNDLED_VERSIONS: &[(&str, &str, &str, &str)] = &[
+    ("JEMALLOC_5_3", "5.3", "jemalloc-5.3", "configure/5.3"),
+    ("JEMALLOC_5_2", "5.2", "jemalloc-5.2", "configure/5.2"),
+    ("", "5.1", "jemalloc", "configure"),
+];
+
+// The files and directories of the bundled `jemalloc` sources used by the
+// build:
+const JEMALLOC_SOURCES: &[&str] = &[
+    "Makefile.in",
+    "bin",
+    "build-aux",
+    "config.stamp.in",
+    "doc",
+    "include",
+    "jemalloc.pc.in",
+    "src",
+    "test",
+];
+
+// `cfg`s set if `jemalloc` is at least the given version, gating the bindings
+// added by that version:
+const VERSION_CFGS: &[(&str, (u32, u32))] = &[("jemalloc_5_2", (5, 2)), ("jemalloc_5_3", (5, 3))];
+
+// Sanitizers intercepting `malloc`, which cannot check allocations made by
+// `jemalloc`, see `JEMALLOC_SYS_SANITIZER_MODE`:
+const ALLOCATOR_SANITIZERS: &[&str] = &["address", "hwaddress", "leak", "memory", "thread"];
+
+// Targets on which `jemallocator` can forward to the system allocator when
+// built with a sanitizer:
+const SANITIZER_FALLBACK_TARGETS: &[&str] = &["linux", "android", "darwin"];
+
 macro_rules! info {
     ($($args:tt)*) => { println!($($args)*) }
 }
@@ -77,9 +182,55 @@ fn main() {
         warning!("jemalloc support for `{}` is untested", target);
     }
 
+    let shared = env::var("CARGO_FEATURE_SHARED_LIBRARY").is_ok();
+
     let mut use_prefix =
         env::var("CARGO_FEATURE_UNPREFIXED_MALLOC_ON_SUPPORTED_PLATFORMS").is_err();
 
+    let sanitizers = sanitizers();
+    let instrument = if sanitizers.is_empty() {
+        false
+    } else {
+        info!("sanitizers: {:?}", sanitizers);
+        println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_SANITIZER_MODE");
+        let mode = env::var("JEMALLOC_SYS_SANITIZER_MODE").unwrap_or_default();
+        match mode.as_str() {
+            "" | "fallback"
+                if SANITIZER_FALLBACK_TARGETS
+                    .iter()
+                    .any(|i| target.contains(i)) =>
+            {
+                warning!(
+                    "built with `-Zsanitizer={}`: `Jemalloc` forwards to the system allocator",
+                    sanitizers.join(",")
+                );
+                println!("cargo:sanitizer_fallback={}", sanitizers.join(","));
+                // `jemalloc` is still linked for `jemalloc-ctl`, but it must
+                // not export the unprefixed symbols, which the sanitizer
+                // runtimes intercept for the system allocator:
+                use_prefix = true;
+                false
+            }
+            "" | "fallback" | "instrument" => {
+                if mode != "instrument" {
+                    warning!(
+                        "the system allocator fallback is not supported on `{}` => \
+                         instrumenting `jemalloc` with `-fsanitize={}`",
+                        target,
+                        sanitizers.join(",")
+                    );
+                }
+                // the sanitizer runtimes interpose the unprefixed symbols:
+                use_prefix = true;
+                true
+            }
+            _ => panic!(
+                "`JEMALLOC_SYS_SANITIZER_MODE={}` is not one of `fallback` or `instrument`",
+                mode
+            ),
+        }
+    };
+
     if !use_prefix && NO_UNPREFIXED_MALLOC.iter().any(|i| target.contains(i)) {
         warning!(
             "Unprefixed `malloc` requested on unsupported platform `{}` => using prefixed `malloc`",
@@ -88,11 +239,51 @@ fn main() {
         use_prefix = true;
     }
 
-    // this has to occur before the early return when JEMALLOC_OVERRIDE is set
-    if use_prefix {
-        println!("cargo:rustc-cfg=prefixed");
+    // rebuild when the symbol prefix or a `configure` option changes:
+    println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_PREFIX");
+    for &(option, _) in VALUE_OPTIONS {
+        println!("cargo:rerun-if-env-changed={}", option_env_var(option));
     }
 
+    let custom_prefix = env::var("JEMALLOC_SYS_PREFIX").ok();
+    let prefix = if let Some(ref prefix) = custom_prefix {
+        info!("JEMALLOC_SYS_PREFIX={}", prefix);
+        assert!(
+            is_symbol_prefix(prefix),
+            "`JEMALLOC_SYS_PREFIX={}` is not a valid C identifier prefix",
+            prefix
+        );
+        Some(prefix.clone())
+    } else if use_prefix {
+        Some(DEFAULT_PREFIX.to_string())
+    } else {
+        None
+    };
+
+    if instrument
+        && (env::var_os("JEMALLOC_SYS_USE_PKG_CONFIG").is_some()
+            || env::var_os("JEMALLOC_OVERRIDE").is_some())
+    {
+        warning!(
+            "`jemalloc` is not built by `jemalloc-sys` and cannot be instrumented with `-fsanitize={}`",
+            sanitizers.join(",")
+        );
+    }
+
+    // the prefix of a system `jemalloc` is detected from its headers instead
+    println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_USE_PKG_CONFIG");
+    if env::var_os("JEMALLOC_SYS_USE_PKG_CONFIG").is_some() {
+        info!("JEMALLOC_SYS_USE_PKG_CONFIG set");
+        let (prefix, header) = link_system_jemalloc(prefix.as_deref());
+        write_configure_info(&out_dir, None);
+        let cfgs = write_build_info(&out_dir, &prefix, Some(&header), None);
+        generate_binding_tests(header.parent().unwrap().parent().unwrap(), &cfgs);
+        return;
+    }
+
+    // this has to occur before the early return when JEMALLOC_OVERRIDE is set
+    emit_prefix(prefix.as_deref());
+
     if let Some(jemalloc) = env::var_os("JEMALLOC_OVERRIDE") {
         info!("jemalloc override set");
         let jemalloc = PathBuf::from(jemalloc);
@@ -113,22 +304,53 @@ fn main() {
             "dylib"
         };
         println!("cargo:rustc-link-lib={}={}", kind, &stem[3..]);
+        if cfg!(feature = "verify_bindings") {
+            panic!("the `verify_bindings` feature requires the `jemalloc` headers and cannot be used with `JEMALLOC_OVERRIDE`");
+        }
+        write_configure_info(&out_dir, None);
+        write_build_info(&out_dir, prefix.as_deref().unwrap_or(""), None, None);
         return;
     }
 
+    assert!(
+        !shared || !target.contains("windows"),
+        "the `shared_library` feature is not supported on `{}`",
+        target
+    );
+
     fs::create_dir_all(&build_dir).unwrap();
     // Disable -Wextra warnings - jemalloc doesn't compile free of warnings with
     // it enabled: https://github.com/jemalloc/jemalloc/issues/1196
     let compiler = cc::Build::new().extra_warnings(false).get_compiler();
+    // flags passed in addition to the default flags of the C compiler:
+    let mut extra_cflags = Vec::new();
+    if instrument {
+        extra_cflags.push(format!("-fsanitize={}", sanitizers.join(",")));
+        extra_cflags.push("-fno-omit-frame-pointer".to_string());
+    }
     let cflags = compiler
         .args()
         .iter()
         .map(|s| s.to_str().unwrap())
+        .chain(extra_cflags.iter().map(String::as_str))
         .collect::<Vec<_>>()
         .join(" ");
     info!("CC={:?}", compiler.path());
     info!("CFLAGS={:?}", cflags);
 
+    // strip the build directory from the debug info and the `__FILE__` of the
+    // assertions, so that the library does not depend on where it is built:
+    let mut build_cflags = cflags.clone();
+    let file_prefix_map = format!("-ffile-prefix-map={}=/jemalloc-sys", out_dir.display());
+    if cc::Build::new()
+        .is_flag_supported(file_prefix_map.as_str())
+        .unwrap_or(false)
+    {
+        build_cflags.push(' ');
+        build_cflags.push_str(&file_prefix_map);
+        extra_cflags.push(file_prefix_map);
+    }
+
     assert!(out_dir.exists(), "OUT_DIR does not exist");
     let (jemalloc_repo_dir, run_autoconf) = if env::var("JEMALLOC_SYS_GIT_DEV_BRANCH").is_ok() {
         let jemalloc_repo = out_dir.join("jemalloc_repo");
@@ -145,7 +367,16 @@ fn main() {
         run(&mut cmd);
         (jemalloc_repo, true)
     } else {
-        (PathBuf::from("jemalloc"), false)
+        let (version, src_dir, _) = bundled_version();
+        info!("bundled jemalloc version: {}", version);
+        assert!(
+            Path::new(src_dir).join("src").exists(),
+            "the sources of the bundled `jemalloc` {} are missing from `{}`, \
+             run `git submodule update --init`",
+            version,
+            src_dir
+        );
+        (PathBuf::from(src_dir), false)
     };
     info!("JEMALLOC_REPO_DIR={:?}", jemalloc_repo_dir);
 
@@ -201,7 +432,7 @@ fn main() {
 
             if verify_configure {
                 let current = read_content(&jemalloc_src_dir.join(f));
-                let reference = read_content(&Path::new("configure").join(f));
+                let reference = read_content(&Path::new(bundled_version().2).join(f));
                 assert_eq!(
                     current, reference,
                     "the current and reference configuration files \"{}\" differ",
@@ -212,8 +443,22 @@ fn main() {
     } else {
         // Copy the configuration files to jemalloc's source directory
         for f in &config_files {
-            fs::copy(Path::new("configure").join(f), jemalloc_src_dir.join(f))
-                .expect("failed to copy config file to OUT_DIR");
+            fs::copy(
+                Path::new(bundled_version().2).join(f),
+                jemalloc_src_dir.join(f),
+            )
+            .expect("failed to copy config file to OUT_DIR");
+        }
+    }
+
+    // Outside of a git repository, e.g., in `OUT_DIR`, `configure` reads the
+    // version from the `VERSION` file of the build directory, or else of the
+    // sources, and reports 0.0.0 without either:
+    let version_file = Path::new(bundled_version().2).join("VERSION");
+    if !run_autoconf && version_file.exists() {
+        for dir in &[&jemalloc_src_dir, &build_dir] {
+            fs::copy(&version_file, dir.join("VERSION"))
+                .expect("failed to copy VERSION to OUT_DIR");
         }
     }
 
@@ -229,15 +474,17 @@ fn main() {
     )
     .current_dir(&build_dir)
     .env("CC", compiler.path())
-    .env("CFLAGS", cflags.clone())
-    .env("LDFLAGS", cflags.clone())
-    .env("CPPFLAGS", cflags.clone())
-    .arg("--disable-cxx");
+    .env("CFLAGS", build_cflags.clone())
+    .env("LDFLAGS", build_cflags.clone())
+    .env("CPPFLAGS", build_cflags.clone());
+
+    // collect the `configure` options:
+    let mut options = vec!["--disable-cxx".to_string()];
 
     if target.contains("ios") {
         // newer iOS deviced have 16kb page sizes:
         // closed: https://github.com/gnzlbg/jemallocator/issues/68
-        cmd.arg("--with-lg-page=14");
+        options.push("--with-lg-page=14".to_string());
     }
 
     // collect `malloc_conf` string:
@@ -273,98 +520,144 @@ fn main() {
 
     if !malloc_conf.is_empty() {
         info!("--with-malloc-conf={}", malloc_conf);
-        cmd.arg(format!("--with-malloc-conf={}", malloc_conf));
-    }
-
-    if let Ok(lg_page) = env::var("JEMALLOC_SYS_WITH_LG_PAGE") {
-        info!("--with-lg-page={}", lg_page);
-        cmd.arg(format!("--with-lg-page={}", lg_page));
+        options.push(format!("--with-malloc-conf={}", malloc_conf));
     }
 
-    if let Ok(lg_hugepage) = env::var("JEMALLOC_SYS_WITH_LG_HUGEPAGE") {
-        info!("--with-lg-hugepage={}", lg_hugepage);
-        cmd.arg(format!("--with-lg-hugepage={}", lg_hugepage));
-    }
-
-    if let Ok(lg_quantum) = env::var("JEMALLOC_SYS_WITH_LG_QUANTUM") {
-        info!("--with-lg-quantum={}", lg_quantum);
-        cmd.arg(format!("--with-lg-quantum={}", lg_quantum));
+    for &(option, validate) in VALUE_OPTIONS {
+        let var = option_env_var(option);
+        if let Ok(value) = env::var(&var) {
+            if let Err(e) = validate(&value) {
+                panic!("invalid `{}={}`: {}", var, value, e);
+            }
+            info!("{}={}", option, value);
+            options.push(format!("{}={}", option, value));
+        }
     }
 
-    if let Ok(lg_vaddr) = env::var("JEMALLOC_SYS_WITH_LG_VADDR") {
-        info!("--with-lg-vaddr={}", lg_vaddr);
-        cmd.arg(format!("--with-lg-vaddr={}", lg_vaddr));
+    if let Some(ref prefix) = prefix {
+        options.push(format!("--with-jemalloc-prefix={}", prefix));
+        info!("--with-jemalloc-prefix={}", prefix);
     }
 
-    if use_prefix {
-        cmd.arg("--with-jemalloc-prefix=_rjem_");
-        info!("--with-jemalloc-prefix=_rjem_");
+    if let Some(ref prefix) = custom_prefix {
+        // a custom prefix also keeps the internal symbols of several copies
+        // of `jemalloc` in the same program apart
+        options.push(format!("--with-private-namespace={}", prefix));
+    } else {
+        options.push("--with-private-namespace=_rjem_".to_string());
     }
 
-    cmd.arg("--with-private-namespace=_rjem_");
-
-    if env::var("CARGO_FEATURE_DEBUG").is_ok() {
-        info!("CARGO_FEATURE_DEBUG set");
-        cmd.arg("--enable-debug");
+    for &(feature, option) in FEATURE_OPTIONS {
+        if env::var(format!("CARGO_FEATURE_{}", feature)).is_ok() {
+            info!("CARGO_FEATURE_{} set", feature);
+            options.push(option.to_string());
+        }
     }
+    validate_feature_options(&target);
 
-    if env::var("CARGO_FEATURE_PROFILING").is_ok() {
-        info!("CARGO_FEATURE_PROFILING set");
-        cmd.arg("--enable-prof");
-    }
+    options.push(format!("--host={}", gnu_target(&target)));
+    options.push(format!("--build={}", gnu_target(&host)));
+    write_configure_info(&out_dir, Some(&options));
 
-    if env::var("CARGO_FEATURE_STATS").is_ok() {
-        info!("CARGO_FEATURE_STATS set");
-        cmd.arg("--enable-stats");
-    }
+    // a build is reused from the cache if jemalloc's sources, the options, and
+    // the compiler are the same:
+    println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_CACHE_DIR");
+    let cache_entry = env::var_os("JEMALLOC_SYS_CACHE_DIR").map(|dir| {
+        PathBuf::from(dir).join(cache_key(
+            &jemalloc_repo_dir,
+            &target,
+            compiler.path(),
+            &cflags,
+            &options,
+            shared,
+        ))
+    });
+    let cached = match cache_entry {
+        Some(ref entry) if entry.exists() => {
+            info!("restoring jemalloc from the cache: {:?}", entry);
+            restore_cache_entry(entry, &out_dir);
+            true
+        }
+        _ => false,
+    };
 
-    if env::var("CARGO_FEATURE_DISABLE_INITIAL_EXEC_TLS").is_ok() {
-        info!("CARGO_FEATURE_DISABLE_INITIAL_EXEC_TLS set");
-        cmd.arg("--disable-initial-exec-tls");
+    let pregenerated = if cached {
+        None
+    } else {
+        pregenerated_config(&target, &options, run_autoconf, shared)
+    };
+    if let Some(ref config) = pregenerated {
+        build_with_cc(config, &jemalloc_src_dir, &out_dir, &target, &extra_cflags);
     }
 
-    cmd.arg(format!("--host={}", gnu_target(&target)));
-    cmd.arg(format!("--build={}", gnu_target(&host)));
-    cmd.arg(format!("--prefix={}", out_dir.display()));
+    if !cached && pregenerated.is_none() {
+        cmd.args(&options);
+        cmd.arg(format!("--prefix={}", out_dir.display()));
 
-    run(&mut cmd);
-
-    // Make:
-    let make = make_cmd(&host);
-    run(Command::new(make)
-        .current_dir(&build_dir)
-        .arg("srcroot=../jemalloc/")
-        .arg("-j")
-        .arg(num_jobs.clone()));
+        run(&mut cmd);
 
-    if env::var("JEMALLOC_SYS_RUN_JEMALLOC_TESTS").is_ok() {
-        info!("Building and running jemalloc tests...");
-        // Make tests:
+        // Make:
+        let make = make_cmd(&host);
         run(Command::new(make)
             .current_dir(&build_dir)
             .arg("srcroot=../jemalloc/")
             .arg("-j")
-            .arg(num_jobs.clone())
-            .arg("tests"));
+            .arg(num_jobs.clone()));
+
+        if env::var("JEMALLOC_SYS_RUN_JEMALLOC_TESTS").is_ok() {
+            info!("Building and running jemalloc tests...");
+            // Make tests:
+            run(Command::new(make)
+                .current_dir(&build_dir)
+                .arg("srcroot=../jemalloc/")
+                .arg("-j")
+                .arg(num_jobs.clone())
+                .arg("tests"));
+
+            // Run tests:
+            run(Command::new(make)
+                .current_dir(&build_dir)
+                .arg("srcroot=../jemalloc/")
+                .arg("check"));
+        }
 
-        // Run tests:
+        // Make install:
         run(Command::new(make)
             .current_dir(&build_dir)
             .arg("srcroot=../jemalloc/")
-            .arg("check"));
-    }
+            .arg(if shared {
+                "install_lib_shared"
+            } else {
+                "install_lib_static"
+            })
+            .arg("install_include")
+            .arg("-j")
+            .arg(num_jobs.clone()));
+
+        if let Some(ref entry) = cache_entry {
+            info!("storing jemalloc in the cache: {:?}", entry);
+            store_cache_entry(entry, &out_dir);
+        }
 
-    // Make install:
-    run(Command::new(make)
-        .current_dir(&build_dir)
-        .arg("srcroot=../jemalloc/")
-        .arg("install_lib_static")
-        .arg("install_include")
-        .arg("-j")
-        .arg(num_jobs.clone()));
+        if let Some(dir) = env::var_os("JEMALLOC_SYS_EXPORT_CONFIG") {
+            assert!(
+                !run_autoconf,
+                "`JEMALLOC_SYS_EXPORT_CONFIG` requires the `jemalloc` submodule"
+            );
+            export_config(&PathBuf::from(dir).join(&target), &build_dir, &options);
+        }
+    }
 
     println!("cargo:root={}", out_dir.display());
 
+    let cfgs = write_build_info(
+        &out_dir,
+        prefix.as_deref().unwrap_or(""),
+        Some(&out_dir.join("include/jemalloc/jemalloc.h")),
+        Some(&build_dir.join("include/jemalloc/internal/jemalloc_internal_defs.h")),
+    );
+    generate_binding_tests(&out_dir.join("include"), &cfgs);
+
     // Linkage directives to pull in jemalloc and its dependencies.
     //
     // On some platforms we need to be sure to link in `pthread` which jemalloc
@@ -372,18 +665,789 @@ fn main() {
     // Currently jemalloc is compiled with gcc which will generate calls to
     // intrinsics that are libgcc specific (e.g. those intrinsics aren't present in
     // libcompiler-rt), so link that in to get that support.
-    if target.contains("windows") {
+    if shared {
+        // The installed library is linked: its install name on macOS is the
+        // path it is installed to. It is not installed to a directory the
+        // dynamic linker searches, so it is found via the `rpath` of the tests
+        // of this crate, and dependents can add it from `DEP_JEMALLOC_LIBDIR`.
+        let lib_dir = out_dir.join("lib");
+        println!("cargo:rustc-link-lib=dylib=jemalloc");
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}", lib_dir.display());
+        println!("cargo:libdir={}", lib_dir.display());
+    } else if target.contains("windows") {
         println!("cargo:rustc-link-lib=static=jemalloc");
+        println!("cargo:rustc-link-search=native={}/lib", build_dir.display());
     } else {
         println!("cargo:rustc-link-lib=static=jemalloc_pic");
+        println!("cargo:rustc-link-search=native={}/lib", build_dir.display());
     }
-    println!("cargo:rustc-link-search=native={}/lib", build_dir.display());
     if target.contains("android") {
         println!("cargo:rustc-link-lib=gcc");
     } else if !target.contains("windows") {
         println!("cargo:rustc-link-lib=pthread");
     }
-    println!("cargo:rerun-if-changed=jemalloc");
+    // the build only depends on these files of the bundled `jemalloc`:
+    if !run_autoconf {
+        for f in JEMALLOC_SOURCES {
+            println!(
+                "cargo:rerun-if-changed={}",
+                jemalloc_repo_dir.join(f).display()
+            );
+        }
+        for f in &["configure", "VERSION"] {
+            let path = Path::new(bundled_version().2).join(f);
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
+
+/// Emits the `prefixed` cfg and the symbol prefix used by the bindings.
+///
+/// The prefix is also exported to dependent build scripts as
+/// `DEP_JEMALLOC_PREFIX`, which is empty for unprefixed symbols.
+fn emit_prefix(prefix: Option<&str>) {
+    if let Some(prefix) = prefix {
+        println!("cargo:rustc-cfg=prefixed");
+        println!("cargo:rustc-env=JEMALLOC_SYS_PREFIX={}", prefix);
+    }
+    println!("cargo:prefix={}", prefix.unwrap_or(""));
+}
+
+/// Returns the pre-generated configuration to build `jemalloc` with the `cc`
+/// backend, if `JEMALLOC_SYS_BUILD_BACKEND` selects it.
+///
+/// A configuration can only be used if it was generated for the same
+/// `jemalloc` version, target, and `configure` `options`.
+fn pregenerated_config(
+    target: &str,
+    options: &[String],
+    run_autoconf: bool,
+    shared: bool,
+) -> Option<PathBuf> {
+    println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_BUILD_BACKEND");
+    println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_RUN_JEMALLOC_TESTS");
+    println!("cargo:rerun-if-env-changed=JEMALLOC_SYS_EXPORT_CONFIG");
+    let backend = env::var("JEMALLOC_SYS_BUILD_BACKEND").unwrap_or_default();
+    // the configurations were detected on another machine, so they are only
+    // used on request:
+    let required = match backend.as_str() {
+        "" | "autoconf" => return None,
+        "auto" => false,
+        "cc" => true,
+        _ => panic!(
+            "`JEMALLOC_SYS_BUILD_BACKEND={}` is not one of `autoconf`, `auto`, or `cc`",
+            backend
+        ),
+    };
+
+    let dir = Path::new(PREGENERATED_CONFIG_DIR).join(target);
+    let read = |f: &str| fs::read_to_string(dir.join(f)).unwrap_or_default();
+    let mismatch = if run_autoconf {
+        Some("the `jemalloc` dev branch is built")
+    } else if shared {
+        Some("the `cc` backend cannot build a shared library")
+    } else if env::var_os("JEMALLOC_SYS_RUN_JEMALLOC_TESTS").is_some() {
+        Some("`jemalloc`'s tests are run with `make`")
+    } else if env::var_os("JEMALLOC_SYS_EXPORT_CONFIG").is_some() {
+        Some("the configuration is exported from a `configure` build")
+    } else if !dir.exists() {
+        Some("there is no pre-generated configuration for the target")
+    } else if read("VERSION").trim() != read_version() {
+        Some("the configuration was generated for another `jemalloc` version")
+    } else if read("ARGS").lines().ne(config_args(options)) {
+        Some("the configuration was generated with other `configure` options")
+    } else {
+        None
+    };
+    match mismatch {
+        Some(reason) if required => panic!(
+            "`JEMALLOC_SYS_BUILD_BACKEND=cc` cannot build `jemalloc` for `{}`: {}",
+            target, reason
+        ),
+        Some(reason) => {
+            info!("not using the `cc` backend: {}", reason);
+            None
+        }
+        None => Some(dir),
+    }
+}
+
+/// Returns the version, the source directory, and the `configure` directory
+/// of the bundled `jemalloc` selected by the cargo features.
+///
+/// If several releases are selected, the newest is used.
+fn bundled_version() -> (&'static str, &'static str, &'static str) {
+    BUNDLED_VERSIONS
+        .iter()
+        .find(|v| v.0.is_empty() || env::var(format!("CARGO_FEATURE_{}", v.0)).is_ok())
+        .map(|&(_, version, src_dir, configure_dir)| (version, src_dir, configure_dir))
+        .unwrap()
+}
+
+/// Returns the full version of the bundled `jemalloc`.
+fn read_version() -> String {
+    let path = Path::new(bundled_version().2).join("VERSION");
+    fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read `{}`: {}", path.display(), e))
+        .trim()
+        .to_string()
+}
+
+/// Returns the `configure` options a pre-generated configuration depends on.
+///
+/// `--build` is ignored, the configuration only depends on the target.
+fn config_args(options: &[String]) -> impl Iterator<Item = &str> {
+    options
+        .iter()
+        .map(String::as_str)
+        .filter(|o| !o.starts_with("--build="))
+}
+
+/// Builds `jemalloc` with `cc` using the pre-generated configuration in
+/// `config`, producing the same library and headers as `make install`.
+fn build_with_cc(
+    config: &Path,
+    src_dir: &Path,
+    out_dir: &Path,
+    target: &str,
+    extra_cflags: &[String],
+) {
+    info!("building jemalloc with the `cc` backend: {:?}", config);
+    println!("cargo:rerun-if-changed={}", config.display());
+    let build_dir = out_dir.join("build");
+    copy(&config.join("include"), &build_dir.join("include"));
+    copy(
+        &build_dir.join("include/jemalloc/jemalloc.h"),
+        &out_dir.join("include/jemalloc/jemalloc.h"),
+    );
+    let defs = defines(&build_dir.join("include/jemalloc/internal/jemalloc_internal_defs.h"));
+
+    let mut sources = fs::read_dir(src_dir.join("src"))
+        .expect("failed to read the jemalloc sources")
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension() == Some("c".as_ref()))
+        // `zone.c` is the macOS zone allocator:
+        .filter(|p| p.file_name() != Some("zone.c".as_ref()) || defs.contains_key("JEMALLOC_ZONE"))
+        .collect::<Vec<_>>();
+    sources.sort();
+
+    let cflags = fs::read_to_string(config.join("CFLAGS")).expect("failed to read `CFLAGS`");
+    let mut build = cc::Build::new();
+    build
+        .extra_warnings(false)
+        .cargo_metadata(false)
+        .out_dir(build_dir.join("lib"))
+        .include(build_dir.join("include"))
+        .include(src_dir.join("include"))
+        .files(sources);
+    for flag in cflags
+        .split_whitespace()
+        .chain(extra_cflags.iter().map(String::as_str))
+    {
+        build.flag(flag);
+    }
+    build.compile(if target.contains("windows") {
+        "jemalloc"
+    } else {
+        "jemalloc_pic"
+    });
+}
+
+/// Exports the configuration generated by `configure` in `build_dir` to `dir`
+/// for the `cc` build backend.
+fn export_config(dir: &Path, build_dir: &Path, options: &[String]) {
+    info!("exporting the jemalloc configuration to {:?}", dir);
+    if dir.exists() {
+        fs::remove_dir_all(dir).expect("failed to remove the old configuration");
+    }
+    copy(&build_dir.join("include"), &dir.join("include"));
+
+    // the flags detected by `configure`, without the flags passed to it:
+    let makefile =
+        fs::read_to_string(build_dir.join("Makefile")).expect("failed to read the `Makefile`");
+    let var = |name: &str| {
+        makefile
+            .lines()
+            .filter_map(|l| l.split_once(":="))
+            .find(|(n, _)| n.trim() == name)
+            .map(|(_, v)| v.trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut cflags: Vec<String> = vec![var("CONFIGURE_CFLAGS"), var("PIC_CFLAGS")];
+    cflags.extend(
+        var("CPPFLAGS")
+            .split_whitespace()
+            .filter(|f| f.starts_with("-D"))
+            .map(String::from),
+    );
+
+    let write = |f: &str, contents: String| {
+        fs::write(dir.join(f), contents)
+            .unwrap_or_else(|e| panic!("failed to write `{}`: {}", f, e))
+    };
+    write("CFLAGS", cflags.join(" ") + "\n");
+    write("VERSION", read_version() + "\n");
+    write(
+        "ARGS",
+        config_args(options).map(|o| format!("{}\n", o)).collect(),
+    );
+}
+
+/// Returns the name of the cache entry of a `jemalloc` build.
+///
+/// The name is a hash of the sources in `src_dir`, the compiler, the options,
+/// and whether a shared library is built. It does not depend on the build directory, so that cache entries
+/// can be shared across machines.
+fn cache_key(
+    src_dir: &Path,
+    target: &str,
+    cc: &Path,
+    cflags: &str,
+    options: &[String],
+    shared: bool,
+) -> String {
+    // FNV-1a, which unlike `DefaultHasher` is stable across Rust versions
+    struct Hasher(u64);
+    impl Hasher {
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes.iter().chain(&[0xff]) {
+                self.0 ^= u64::from(b);
+                self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+    fn files(dir: &Path, out: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).expect("failed to read the jemalloc sources") {
+            let path = entry.unwrap().path();
+            if path.file_name() == Some(".git".as_ref()) {
+                continue;
+            } else if path.is_dir() {
+                files(&path, out);
+            } else {
+                out.push(path);
+            }
+        }
+    }
+
+    let mut h = Hasher(0xcbf2_9ce4_8422_2325);
+    h.write(env!("CARGO_PKG_VERSION").as_bytes());
+    h.write(target.as_bytes());
+    h.write(cc.to_string_lossy().as_bytes());
+    h.write(cflags.as_bytes());
+    for option in options {
+        h.write(option.as_bytes());
+    }
+    h.write(&[shared as u8]);
+    let mut sources = vec![
+        Path::new(bundled_version().2).join("configure"),
+        Path::new(bundled_version().2).join("VERSION"),
+    ];
+    files(src_dir, &mut sources);
+    sources[2..].sort();
+    for path in &sources {
+        let name = path.strip_prefix(src_dir).unwrap_or(path);
+        h.write(name.to_string_lossy().replace('\\', "/").as_bytes());
+        h.write(&fs::read(path).expect("failed to read the jemalloc sources"));
+    }
+    format!("{}-{:016x}", target, h.0)
+}
+
+// The files of a `jemalloc` build stored in a cache entry, relative to
+// `OUT_DIR`:
+const CACHED_FILES: &[&str] = &[
+    "build/lib",
+    "build/include/jemalloc/internal/jemalloc_internal_defs.h",
+    "include",
+    "lib",
+];
+
+/// Copies the build in `out_dir` to the cache `entry`.
+///
+/// The entry is written to a temporary directory first and then renamed, so
+/// that concurrent builds never observe incomplete entries.
+fn store_cache_entry(entry: &Path, out_dir: &Path) {
+    let tmp = PathBuf::from(format!("{}.tmp{}", entry.display(), std::process::id()));
+    for f in CACHED_FILES {
+        copy(&out_dir.join(f), &tmp.join(f));
+    }
+    if fs::rename(&tmp, entry).is_err() {
+        // another build stored the same entry first
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
+
+/// Copies the build in the cache `entry` to `out_dir`.
+fn restore_cache_entry(entry: &Path, out_dir: &Path) {
+    for f in CACHED_FILES {
+        copy(&entry.join(f), &out_dir.join(f));
+    }
+}
+
+/// Copies the file or directory `from` to `to`, creating its parent
+/// directories.
+fn copy(from: &Path, to: &Path) {
+    let parent = to.parent().unwrap();
+    fs::create_dir_all(parent).expect("failed to create a cache directory");
+    if from.is_dir() {
+        let mut options = fs_extra::dir::CopyOptions::new();
+        options.overwrite = true;
+        fs_extra::dir::copy(from, parent, &options)
+            .unwrap_or_else(|e| panic!("failed to copy `{}`: {}", from.display(), e));
+    } else {
+        fs::copy(from, to).unwrap_or_else(|e| panic!("failed to copy `{}`: {}", from.display(), e));
+    }
+}
+
+/// Returns the sanitizers intercepting `malloc` that the crate is built with,
+/// e.g., `address` for `-Zsanitizer=address`.
+fn sanitizers() -> Vec<String> {
+    let mut sanitizers: Vec<String> = env::var("CARGO_CFG_SANITIZE")
+        .unwrap_or_default()
+        .split(',')
+        .map(String::from)
+        .collect();
+    // `cfg(sanitize)` is not exposed by all toolchains, so also check the flags:
+    let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let mut flags = rustflags.split('\x1f');
+    while let Some(flag) = flags.next() {
+        let flag = if flag == "-Z" {
+            flags.next().unwrap_or("")
+        } else {
+            flag.trim_start_matches("-Z")
+        };
+        if let Some(list) = flag.strip_prefix("sanitizer=") {
+            sanitizers.extend(list.split(',').map(String::from));
+        }
+    }
+    sanitizers.retain(|s| ALLOCATOR_SANITIZERS.contains(&s.as_str()));
+    sanitizers.sort();
+    sanitizers.dedup();
+    sanitizers
+}
+
+/// Returns `true` if `prefix` can start a C identifier.
+fn is_symbol_prefix(prefix: &str) -> bool {
+    let mut chars = prefix.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Links against the system `jemalloc` found via `pkg-config`.
+///
+/// The symbol prefix of the library is detected from its `jemalloc.h` header
+/// and overrides the `requested` one. Returns the prefix and the header.
+fn link_system_jemalloc(requested: Option<&str>) -> (String, PathBuf) {
+    let lib = pkg_config::Config::new()
+        .atleast_version(SYSTEM_MIN_VERSION)
+        .probe("jemalloc")
+        .unwrap_or_else(|e| panic!("failed to find a system `jemalloc` with pkg-config: {}", e));
+    info!("system jemalloc version: {}", lib.version);
+    assert!(
+        lib.version.split('.').next() == Some(SYSTEM_MAJOR_VERSION),
+        "the system `jemalloc` version {} is not supported, the bindings require version {}.x",
+        lib.version,
+        SYSTEM_MAJOR_VERSION
+    );
+
+    // pkg-config omits the default include paths:
+    let header = lib
+        .include_paths
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(vec![
+            Path::new("/usr/local/include"),
+            Path::new("/usr/include"),
+        ])
+        .map(|p| p.join("jemalloc").join("jemalloc.h"))
+        .find(|p| p.exists())
+        .expect("failed to find the `jemalloc/jemalloc.h` header of the system `jemalloc`");
+    info!("system jemalloc header: {:?}", header);
+    let prefix = symbol_prefix(&defines(&header)).unwrap_or_else(|| {
+        panic!(
+            "failed to detect the symbol prefix in `{}`",
+            header.display()
+        )
+    });
+    info!("system jemalloc prefix: {:?}", prefix);
+
+    match (prefix.as_str(), requested) {
+        ("", Some(_)) => warning!(
+            "the system `jemalloc` {} is unprefixed => it overrides `malloc`",
+            lib.version
+        ),
+        (p, None) if !p.is_empty() => warning!(
+            "the system `jemalloc` {} is prefixed => `malloc` is not overridden",
+            lib.version
+        ),
+        _ => {}
+    }
+    if let Ok(requested) = env::var("JEMALLOC_SYS_PREFIX") {
+        if !prefix.is_empty() && requested != prefix {
+            warning!(
+                "the system `jemalloc` {} uses the symbol prefix `{}` instead of `{}`",
+                lib.version,
+                prefix,
+                requested
+            );
+        }
+    }
+    emit_prefix(if prefix.is_empty() {
+        None
+    } else {
+        Some(&prefix)
+    });
+    (prefix, header)
+}
+
+/// Returns the prefix of the public symbols of a `jemalloc.h` header.
+///
+/// The header maps the internal names to the public symbols, e.g.,
+/// `#  define je_malloc _rjem_malloc`.
+fn symbol_prefix(defines: &HashMap<String, String>) -> Option<String> {
+    let symbol = defines.get("je_malloc")?;
+    if symbol.ends_with("malloc") {
+        Some(symbol[..symbol.len() - "malloc".len()].to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns the object-like macros defined by the C header at `path`, with
+/// the quotes of string values removed.
+fn defines(path: &Path) -> HashMap<String, String> {
+    let header = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read `{}`: {}", path.display(), e));
+    header
+        .lines()
+        .filter_map(|l| {
+            let l = l.trim_start();
+            if !l.starts_with('#') {
+                return None;
+            }
+            let l = l[1..].trim_start();
+            if !l.starts_with("define") {
+                return None;
+            }
+            let mut words = l["define".len()..].split_whitespace();
+            let name = words.next()?;
+            if name.contains('(') {
+                return None;
+            }
+            let value = words.collect::<Vec<_>>().join(" ");
+            Some((name.to_string(), value.trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// Default base 2 logarithm of the minimum alignment `jemalloc` uses on
+/// `arch` unless configured with `--with-lg-quantum`.
+fn default_lg_quantum(arch: &str) -> Option<u32> {
+    match arch {
+        "arm" | "mips" | "mips64" => Some(3),
+        "x86" | "x86_64" | "aarch64" | "powerpc" | "powerpc64" | "riscv32" | "riscv64"
+        | "s390x" | "sparc64" => Some(4),
+        _ => None,
+    }
+}
+
+/// Writes `build_info.rs` with the constants of `build_info` parsed from the
+/// `jemalloc.h` `header` and the `jemalloc_internal_defs.h` `internal_defs`,
+/// and emits the `jemalloc_{version}` and `jemalloc_{feature}` cfgs.
+///
+/// The cfgs are also exported to dependent build scripts as a comma-separated
+/// list in `DEP_JEMALLOC_CFGS`, and returned.
+fn write_build_info(
+    out_dir: &Path,
+    prefix: &str,
+    header: Option<&Path>,
+    internal_defs: Option<&Path>,
+) -> Vec<String> {
+    use std::fmt::Write;
+
+    let header = header.map(defines).unwrap_or_default();
+    let internal = internal_defs.map(defines);
+    let lg = |name: &str| {
+        internal
+            .as_ref()
+            .and_then(|d| d.get(name))
+            .map(|v| v.parse::<u32>().expect(name))
+    };
+    let lg_quantum = lg("LG_QUANTUM").or_else(|| {
+        internal.as_ref().and_then(|_| {
+            default_lg_quantum(&env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default())
+        })
+    });
+    let option = |v: Option<&String>| match v {
+        Some(v) => format!("Some({:?})", v),
+        None => "None".to_string(),
+    };
+
+    let mut rs = String::new();
+    writeln!(
+        rs,
+        "/// The `jemalloc` version, e.g., `\"5.1.0-0-g61efbda7098de6fe64c362d309824864308c36d4\"`.
+pub const VERSION: Option<&str> = {};
+
+/// The prefix of the `jemalloc` symbols, or an empty string if unprefixed.
+pub const PREFIX: &str = {:?};
+
+/// The base 2 logarithm of the page size.
+pub const LG_PAGE: Option<u32> = {:?};
+
+/// The base 2 logarithm of the huge page size.
+pub const LG_HUGEPAGE: Option<u32> = {:?};
+
+/// The base 2 logarithm of the minimum alignment of allocations.
+pub const LG_QUANTUM: Option<u32> = {:?};
+
+/// The options compiled into `jemalloc`, see `--with-malloc-conf`.
+pub const MALLOC_CONF: Option<&str> = {};",
+        option(header.get("JEMALLOC_VERSION")),
+        prefix,
+        lg("LG_PAGE"),
+        lg("LG_HUGEPAGE"),
+        lg_quantum,
+        option(
+            internal
+                .as_ref()
+                .and_then(|d| d.get("JEMALLOC_CONFIG_MALLOC_CONF"))
+        ),
+    )
+    .unwrap();
+
+    // the version of a `JEMALLOC_OVERRIDE` library is assumed to be the one
+    // selected by the cargo features:
+    let version = header
+        .get("JEMALLOC_VERSION")
+        .map(String::as_str)
+        .unwrap_or_else(|| bundled_version().0);
+    let mut numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse().unwrap_or(0));
+    let version = (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0));
+    let mut cfgs: Vec<String> = VERSION_CFGS
+        .iter()
+        .filter(|&&(_, min)| version >= min)
+        .map(|&(cfg, _)| cfg.to_string())
+        .collect();
+    for &(name, define) in HEADER_FEATURES {
+        let enabled = internal.as_ref().map(|d| d.contains_key(define));
+        if enabled == Some(true) {
+            cfgs.push(format!("jemalloc_{}", name));
+        }
+        writeln!(
+            rs,
+            "
+/// Whether `jemalloc` was built with `{}` (cfg `jemalloc_{}`).
+pub const {}: Option<bool> = {:?};",
+            define,
+            name,
+            name.to_uppercase(),
+            enabled
+        )
+        .unwrap();
+    }
+    writeln!(
+        rs,
+        "
+/// The `cfg` flags of the version and the enabled features, e.g.,
+/// `\"jemalloc_5_2\"` or `\"jemalloc_prof\"`.
+pub const CFGS: &[&str] = &{:?};",
+        cfgs
+    )
+    .unwrap();
+
+    for cfg in &cfgs {
+        println!("cargo:rustc-cfg={}", cfg);
+    }
+    println!("cargo:cfgs={}", cfgs.join(","));
+    info!("build info:\n{}", rs);
+    fs::write(out_dir.join("build_info.rs"), rs).expect("failed to write `build_info.rs`");
+    cfgs
+}
+
+/// Generates the tests of the `verify_bindings` feature, which check the
+/// bindings against the `jemalloc/jemalloc.h` header in `include_dir`.
+///
+/// `ctest` checks the signatures of the functions, the values of the
+/// constants, and the layout of `extent_hooks_s` (`tests/verify_bindings.rs`).
+/// The `MALLOCX_*` flags are function-like macros, which are compiled into C
+/// functions instead (`tests/mallocx_flags.{c,rs}`).
+#[cfg(feature = "verify_bindings")]
+fn generate_binding_tests(include_dir: &Path, cfgs: &[String]) {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    // The parser of `ctest` cannot expand the macros generating the link
+    // names from the symbol prefix, so these are removed, and the C side
+    // refers to the unprefixed names which `JEMALLOC_MANGLE` maps to the
+    // prefixed symbols. `build_info` is not part of the bindings.
+    let bindings = fs::read_to_string("src/lib.rs").expect("failed to read `src/lib.rs`");
+    let mut stripped = String::new();
+    let mut in_link_name = false;
+    for line in bindings.lines() {
+        let l = line.trim();
+        in_link_name |= l.starts_with("#[cfg_attr(") && !l.ends_with(")]")
+            || l.starts_with("#[cfg_attr(prefixed, link_name");
+        if !in_link_name && l != "pub mod build_info;" {
+            stripped.push_str(line);
+            stripped.push('\n');
+        }
+        in_link_name &= !l.ends_with(")]");
+    }
+    let bindings = out_dir.join("verify_bindings_lib.rs");
+    fs::write(&bindings, stripped).expect("failed to write the bindings");
+
+    let mut cfg = ctest2::TestGenerator::new();
+    cfg.header("jemalloc/jemalloc.h")
+        .include(include_dir)
+        .define("JEMALLOC_MANGLE", None)
+        .skip_signededness(|c| c.ends_with("_t"))
+        // the hook types are function types in C but pointers in Rust:
+        .skip_type(|t| t.starts_with("extent_") && t != "extent_hooks_t");
+    // check the bindings of the version being linked:
+    for c in cfgs {
+        cfg.cfg(c, None);
+    }
+    if env::var("TARGET").unwrap().contains("linux") {
+        cfg.skip_fn(|f| f == "malloc_usable_size");
+    }
+    cfg.generate(&bindings, "verify_bindings.rs");
+
+    cc::Build::new()
+        .file("tests/mallocx_flags.c")
+        .include(include_dir)
+        .define("JEMALLOC_MANGLE", None)
+        .compile("mallocx_flags");
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=tests/mallocx_flags.c");
+}
+
+#[cfg(not(feature = "verify_bindings"))]
+fn generate_binding_tests(_include_dir: &Path, _cfgs: &[String]) {}
+
+/// Validates a base 2 logarithm option value.
+fn lg(value: &str, min: u32, max: u32) -> Result<(), String> {
+    match value.parse::<u32>() {
+        Ok(v) if min <= v && v <= max => Ok(()),
+        _ => Err(format!("expected an integer in [{}, {}]", min, max)),
+    }
+}
+
+/// Returns the environment variable setting a `configure` option, e.g.,
+/// `JEMALLOC_SYS_WITH_LG_PAGE` for `--with-lg-page`.
+fn option_env_var(option: &str) -> String {
+    format!(
+        "JEMALLOC_SYS_{}",
+        option
+            .trim_start_matches('-')
+            .replace('-', "_")
+            .to_uppercase()
+    )
+}
+
+/// Warns about cargo features configuring `jemalloc` that have no effect.
+fn validate_feature_options(target: &str) {
+    let enabled = |f: &str| env::var(format!("CARGO_FEATURE_{}", f)).is_ok();
+    if !enabled("PROFILING") {
+        for f in &["DISABLE_PROFILING_LIBGCC", "DISABLE_PROFILING_GCC"] {
+            if enabled(f) {
+                warning!("`{}` has no effect without `profiling`", f.to_lowercase());
+            }
+        }
+    }
+    if enabled("DISABLE_ZONE_ALLOCATOR") && !target.contains("darwin") {
+        warning!(
+            "`disable_zone_allocator` has no effect on `{}`, the zone allocator is macOS specific",
+            target
+        );
+    }
+}
+
+/// Writes `configure.rs` with the constants of `build_info::configure`.
+///
+/// `options` are the options passed to `configure`, or `None` if `jemalloc` is
+/// not built by this script.
+fn write_configure_info(out_dir: &Path, options: Option<&[String]>) {
+    use std::fmt::Write;
+
+    let args = options.unwrap_or(&[]);
+    let mut rs = String::new();
+    writeln!(
+        rs,
+        "/// Whether `jemalloc` was configured and built by `jemalloc-sys`.
+///
+/// If `jemalloc` is not built, e.g., because `JEMALLOC_OVERRIDE` is set, the
+/// other constants are empty.
+pub const CONFIGURED: bool = {};
+
+/// The options passed to `configure`.
+pub const ARGS: &[&str] = &{:?};",
+        options.is_some(),
+        args
+    )
+    .unwrap();
+
+    let value = |option: &str| {
+        let prefix = format!("{}=", option);
+        args.iter()
+            .rev()
+            .find(|a| a.starts_with(&prefix))
+            .map(|a| a[prefix.len()..].to_string())
+    };
+    let malloc_conf = value("--with-malloc-conf").unwrap_or_default();
+    writeln!(
+        rs,
+        "
+/// The value of `--with-malloc-conf`, or an empty string if not set.
+pub const WITH_MALLOC_CONF: &str = {:?};",
+        malloc_conf
+    )
+    .unwrap();
+    for &(option, _) in VALUE_OPTIONS
+        .iter()
+        .filter(|o| o.0.starts_with("--with-lg-"))
+    {
+        let name = option
+            .trim_start_matches('-')
+            .replace('-', "_")
+            .to_uppercase();
+        let value = match value(option) {
+            Some(v) => format!("Some({:?})", v),
+            None => "None".to_string(),
+        };
+        writeln!(
+            rs,
+            "
+/// The value of `{}`, if set.
+pub const {}: Option<&str> = {};",
+            option, name, value
+        )
+        .unwrap();
+    }
+    for &(_, option) in FEATURE_OPTIONS {
+        let name = option
+            .trim_start_matches('-')
+            .replace('-', "_")
+            .to_uppercase();
+        writeln!(
+            rs,
+            "
+/// Whether `jemalloc` was configured with `{}`.
+pub const {}: bool = {};",
+            option,
+            name,
+            args.iter().any(|a| a == option)
+        )
+        .unwrap();
+    }
+
+    info!("configure summary:\n{}", rs);
+    fs::write(out_dir.join("configure.rs"), rs).expect("failed to write `configure.rs`");
 }
 
 fn run(cmd: &mut Command) {
@@ -0,0 +1,33 @@
+//! Information about the `jemalloc` library the bindings are linked against.
+//!
+//! The constants are parsed from the headers generated when building
+//! `jemalloc`. The values that cannot be determined are `None`: when linking a
+//! system `jemalloc` only [`VERSION`] is known, and when linking the library
+//! in `JEMALLOC_OVERRIDE` only [`PREFIX`] is known.
+//!
+//! The features `jemalloc` was built with are also available as `cfg` flags,
+//! e.g., `jemalloc_prof` and `jemalloc_stats`, see [`CFGS`]. These are only set
+//! for `jemalloc-sys` itself, but are exported to the build scripts of the
+//! crates depending on it as `DEP_JEMALLOC_CFGS`, which they can forward:
+//!
+//! ```no_run
+//! // in `main` of build.rs:
+//! let cfgs = std::env::var("DEP_JEMALLOC_CFGS").unwrap_or_default();
+//! for cfg in cfgs.split(',').filter(|c| !c.is_empty()) {
+//!     println!("cargo:rustc-cfg={}", cfg);
+//! }
+//! ```
+//!
+//! [`VERSION`]: constant.VERSION.html
+//! [`PREFIX`]: constant.PREFIX.html
+//! [`CFGS`]: constant.CFGS.html
+
+include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
+
+pub mod configure {
+    //! The options `jemalloc` was configured with.
+    //!
+    //! These are set by the cargo features and the `JEMALLOC_SYS_*`
+    //! environment variables of `jemalloc-sys`; see its README.
+    include!(concat!(env!("OUT_DIR"), "/configure.rs"));
+}
@@ -32,6 +32,8 @@
 //! # Environment variables
 //!
 //! The `MALLOC_CONF` environment variable affects the execution of the allocation functions.
+//! When the symbols are prefixed, the name of the environment variable is
+//! prefixed as well, e.g., `_RJEM_MALLOC_CONF`.
 //!
 //! For the documentation of the [`MALLCTL` namespace visit the jemalloc
 //! documenation][jemalloc_mallctl].
@@ -49,6 +51,8 @@
 
 extern crate libc;
 
+pub mod build_info;
+
 use libc::{c_char, c_int, c_uint, c_void, size_t};
 type c_bool = c_int;
 
@@ -123,6 +127,19 @@ pub fn MALLOCX_ARENA(a: usize) -> c_int {
     (a as c_int).wrapping_add(1).wrapping_shl(20)
 }
 
+/// Arena index that refers to all arenas in the _MALLCTL NAMESPACE_.
+///
+/// It can be used in place of the `<i>` component of keys like
+/// `arena.<i>.purge` or `stats.arenas.<i>.pdirty` to operate on, or to read
+/// merged statistics of, all arenas at once.
+pub const MALLCTL_ARENAS_ALL: size_t = 4096;
+
+/// Arena index that refers to the statistics of destroyed arenas.
+///
+/// It can be used in place of the `<i>` component of `stats.arenas.<i>.*`
+/// keys to read the merged statistics of all destroyed arenas.
+pub const MALLCTL_ARENAS_DESTROYED: size_t = 4097;
+
 extern "C" {
     /// Allocates `size` bytes of uninitialized memory.
     ///
@@ -140,7 +157,7 @@ extern "C" {
     ///
     /// If the space cannot be allocated, a null pointer is returned and `errno`
     /// is set to `ENOMEM`.
-    #[cfg_attr(prefixed, link_name = "_rjem_malloc")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc"))]
     pub fn malloc(size: size_t) -> *mut c_void;
     /// Allocates zero-initialized space for an array of `number` objects, each
     /// of whose size is `size`.
@@ -151,7 +168,7 @@ extern "C" {
     ///
     /// Note: zero-initialized memory need not be the same as the
     /// representation of floating-point zero or a null pointer constant.
-    #[cfg_attr(prefixed, link_name = "_rjem_calloc")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "calloc"))]
     pub fn calloc(number: size_t, size: size_t) -> *mut c_void;
 
     /// Allocates `size` bytes of memory at an address which is a multiple of
@@ -175,7 +192,7 @@ extern "C" {
     /// The behavior is _undefined_ if:
     ///
     /// * `ptr` is null.
-    #[cfg_attr(prefixed, link_name = "_rjem_posix_memalign")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "posix_memalign"))]
     pub fn posix_memalign(ptr: *mut *mut c_void, alignment: size_t, size: size_t) -> c_int;
 
     /// Allocates `size` bytes of memory at an address which is a multiple of
@@ -195,7 +212,7 @@ extern "C" {
     ///
     /// * `alignment` is not a power-of-two
     /// * `size` is not an integral multiple of `alignment`
-    #[cfg_attr(prefixed, link_name = "_rjem_aligned_alloc")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "aligned_alloc"))]
     pub fn aligned_alloc(alignment: size_t, size: size_t) -> *mut c_void;
 
     /// Resizes the previously-allocated memory region referenced by `ptr` to
@@ -234,7 +251,7 @@ extern "C" {
     /// * `ptr` does not match a pointer previously returned by the memory
     ///   allocation functions of this crate, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_realloc")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "realloc"))]
     pub fn realloc(ptr: *mut c_void, size: size_t) -> *mut c_void;
 
     /// Deallocates previously-allocated memory region referenced by `ptr`.
@@ -250,7 +267,7 @@ extern "C" {
     /// * `ptr` does not match a pointer earlier returned by the memory
     ///   allocation functions of this crate, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_free")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "free"))]
     pub fn free(ptr: *mut c_void);
 
     /// Allocates at least `size` bytes of memory according to `flags`.
@@ -270,7 +287,7 @@ extern "C" {
     /// # Safety
     ///
     /// The behavior is _undefined_ if `size == 0`.
-    #[cfg_attr(prefixed, link_name = "_rjem_mallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "mallocx"))]
     pub fn mallocx(size: size_t, flags: c_int) -> *mut c_void;
 
     /// Resizes the previously-allocated memory region referenced by `ptr` to be
@@ -303,7 +320,7 @@ extern "C" {
     /// * `ptr` does not match a pointer earlier returned by
     ///   the memory allocation functions of this crate, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_rallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "rallocx"))]
     pub fn rallocx(ptr: *mut c_void, size: size_t, flags: c_int) -> *mut c_void;
 
     /// Resizes the previously-allocated memory region referenced by `ptr` _in
@@ -344,7 +361,7 @@ extern "C" {
     /// * `ptr` does not match a pointer earlier returned by the memory
     ///   allocation functions of this crate, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_xallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "xallocx"))]
     pub fn xallocx(ptr: *mut c_void, size: size_t, extra: size_t, flags: c_int) -> size_t;
 
     /// Returns the real size of the previously-allocated memory region
@@ -359,7 +376,7 @@ extern "C" {
     /// * `ptr` does not match a pointer earlier returned by the memory
     ///   allocation functions of this crate, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_sallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "sallocx"))]
     pub fn sallocx(ptr: *const c_void, flags: c_int) -> size_t;
 
     /// Deallocates previously-allocated memory region referenced by `ptr`.
@@ -374,7 +391,7 @@ extern "C" {
     ///   allocation functions of this crate, or
     /// * `ptr` is null, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_dallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "dallocx"))]
     pub fn dallocx(ptr: *mut c_void, flags: c_int);
 
     /// Deallocates previously-allocated memory region referenced by `ptr` with
@@ -394,7 +411,7 @@ extern "C" {
     ///   allocation functions of this crate, or
     /// * `ptr` is null, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_sdallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "sdallocx"))]
     pub fn sdallocx(ptr: *mut c_void, size: size_t, flags: c_int);
 
     /// Returns the real size of the allocation that would result from a
@@ -408,7 +425,7 @@ extern "C" {
     /// # Safety
     ///
     /// The behavior is _undefined_ if `size == 0`.
-    #[cfg_attr(prefixed, link_name = "_rjem_nallocx")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "nallocx"))]
     pub fn nallocx(size: size_t, flags: c_int) -> size_t;
 
     /// Returns the real size of the previously-allocated memory region
@@ -433,7 +450,7 @@ extern "C" {
     /// * `ptr` does not match a pointer earlier returned by the memory
     ///   allocation functions of this crate, or
     /// * the memory region referenced by `ptr` has been deallocated.
-    #[cfg_attr(prefixed, link_name = "_rjem_malloc_usable_size")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc_usable_size"))]
     pub fn malloc_usable_size(ptr: *const c_void) -> size_t;
 
     /// General interface for introspecting the memory allocator, as well as
@@ -466,7 +483,7 @@ extern "C" {
     /// directly related to `mallctl` read/write processing.
     ///
     /// [jemalloc_mallctl]: http://jemalloc.net/jemalloc.3.html#mallctl_namespace
-    #[cfg_attr(prefixed, link_name = "_rjem_mallctl")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "mallctl"))]
     pub fn mallctl(
         name: *const c_char,
         oldp: *mut c_void,
@@ -488,11 +505,11 @@ extern "C" {
     /// a complete MIB. For name components that are integers (e.g. the 2 in
     /// arenas.bin.2.size), the corresponding MIB component will always be that
     /// integer.
-    #[cfg_attr(prefixed, link_name = "_rjem_mallctlnametomib")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "mallctlnametomib"))]
     pub fn mallctlnametomib(name: *const c_char, mibp: *mut size_t, miblenp: *mut size_t) -> c_int;
 
     /// Like [`mallctl`] but taking a `mib` as input instead of a name.
-    #[cfg_attr(prefixed, link_name = "_rjem_mallctlbymib")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "mallctlbymib"))]
     pub fn mallctlbymib(
         mib: *const size_t,
         miblen: size_t,
@@ -529,7 +546,7 @@ extern "C" {
     /// Note that thread caching may prevent some statistics from being
     /// completely up to date, since extra locking would be required to merge
     /// counters that track thread cache operations.
-    #[cfg_attr(prefixed, link_name = "_rjem_malloc_stats_print")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc_stats_print"))]
     pub fn malloc_stats_print(
         write_cb: Option<unsafe extern "C" fn(*mut c_void, *const c_char)>,
         cbopaque: *mut c_void,
@@ -546,7 +563,7 @@ extern "C" {
     ///
     /// Please note that doing anything which tries to allocate memory in this
     /// function is likely to result in a crash or deadlock.
-    #[cfg_attr(prefixed, link_name = "_rjem_malloc_message")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc_message"))]
     pub static mut malloc_message:
         Option<unsafe extern "C" fn(cbopaque: *mut c_void, s: *const c_char)>;
 
@@ -576,8 +593,21 @@ extern "C" {
     /// Some options have boolean values (`true`/`false`), others have integer
     /// values (base `8`, `10`, or `16`, depending on prefix), and yet others
     /// have raw string values.
-    #[cfg_attr(prefixed, link_name = "_rjem_malloc_conf")]
+    #[cfg_attr(prefixed, link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc_conf"))]
     pub static malloc_conf: Option<&'static c_char>;
+
+    /// Options string read after all other options, see [`malloc_conf`].
+    ///
+    /// Unlike `malloc_conf`, the options set here override the `MALLOC_CONF`
+    /// environment variable. Requires `jemalloc` 5.3.
+    ///
+    /// [`malloc_conf`]: static.malloc_conf.html
+    #[cfg(jemalloc_5_3)]
+    #[cfg_attr(
+        prefixed,
+        link_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc_conf_2_conf_harder")
+    )]
+    pub static malloc_conf_2_conf_harder: Option<&'static c_char>;
 }
 
 /// Extent lifetime management functions.
@@ -0,0 +1,44 @@
+extern crate jemalloc_sys;
+
+use jemalloc_sys::build_info::{self, configure};
+
+#[test]
+fn configure_options() {
+    if !configure::CONFIGURED {
+        assert!(configure::ARGS.is_empty());
+        return;
+    }
+    assert!(configure::ARGS.contains(&"--disable-cxx"));
+    assert_eq!(configure::ENABLE_PROF, cfg!(feature = "profiling"));
+    assert_eq!(configure::ENABLE_DEBUG, cfg!(feature = "debug"));
+    assert_eq!(configure::DISABLE_FILL, cfg!(feature = "disable_fill"));
+    if let Some(lg_page) = configure::WITH_LG_PAGE {
+        assert!(configure::ARGS.contains(&&*format!("--with-lg-page={}", lg_page)));
+    }
+}
+
+#[test]
+fn header_info() {
+    if !configure::CONFIGURED {
+        assert!(build_info::LG_PAGE.is_none());
+        assert!(build_info::PROF.is_none());
+        assert!(build_info::CFGS.is_empty());
+        return;
+    }
+    assert!(build_info::VERSION.unwrap().starts_with("5."));
+    let lg_page = build_info::LG_PAGE.unwrap();
+    assert!((12..=16).contains(&lg_page));
+    assert_eq!(build_info::PROF, Some(configure::ENABLE_PROF));
+    assert_eq!(build_info::DEBUG, Some(configure::ENABLE_DEBUG));
+    assert_eq!(
+        build_info::CFGS.contains(&"jemalloc_prof"),
+        configure::ENABLE_PROF
+    );
+    assert_eq!(build_info::MALLOC_CONF, Some(configure::WITH_MALLOC_CONF));
+    if let Some(lg_quantum) = configure::WITH_LG_QUANTUM {
+        assert_eq!(
+            build_info::LG_QUANTUM.map(|q| q.to_string()),
+            Some(lg_quantum.to_string())
+        );
+    }
+}
@@ -7,7 +7,10 @@ union U {
 }
 
 #[allow(non_upper_case_globals)]
-#[cfg_attr(prefixed, export_name = "_rjem_malloc_conf")]
+#[cfg_attr(
+    prefixed,
+    export_name = concat!(env!("JEMALLOC_SYS_PREFIX"), "malloc_conf")
+)]
 #[cfg_attr(not(prefixed), no_mangle)]
 pub static malloc_conf: Option<&'static libc::c_char> = Some(unsafe {
     U {
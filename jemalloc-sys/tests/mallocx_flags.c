@@ -0,0 +1,10 @@
+/* The `MALLOCX_*` macros of the header, see `tests/mallocx_flags.rs`. */
+#include <stddef.h>
+#include <jemalloc/jemalloc.h>
+
+int jemalloc_sys_mallocx_lg_align(size_t la) { return MALLOCX_LG_ALIGN(la); }
+int jemalloc_sys_mallocx_align(size_t a) { return MALLOCX_ALIGN(a); }
+int jemalloc_sys_mallocx_zero(void) { return MALLOCX_ZERO; }
+int jemalloc_sys_mallocx_tcache(size_t tc) { return MALLOCX_TCACHE(tc); }
+int jemalloc_sys_mallocx_tcache_none(void) { return MALLOCX_TCACHE_NONE; }
+int jemalloc_sys_mallocx_arena(size_t a) { return MALLOCX_ARENA(a); }
@@ -0,0 +1,48 @@
+//! Checks the `MALLOCX_*` flags against the macros of the
+//! `jemalloc/jemalloc.h` header, see the `verify_bindings` feature.
+#![cfg(feature = "verify_bindings")]
+
+extern crate jemalloc_sys;
+extern crate libc;
+
+use jemalloc_sys::*;
+use libc::{c_int, size_t};
+
+extern "C" {
+    fn jemalloc_sys_mallocx_lg_align(la: size_t) -> c_int;
+    fn jemalloc_sys_mallocx_align(a: size_t) -> c_int;
+    fn jemalloc_sys_mallocx_zero() -> c_int;
+    fn jemalloc_sys_mallocx_tcache(tc: size_t) -> c_int;
+    fn jemalloc_sys_mallocx_tcache_none() -> c_int;
+    fn jemalloc_sys_mallocx_arena(a: size_t) -> c_int;
+}
+
+#[test]
+fn align() {
+    for la in 0..usize::max_value().count_ones() as usize {
+        unsafe {
+            assert_eq!(MALLOCX_LG_ALIGN(la), jemalloc_sys_mallocx_lg_align(la));
+            assert_eq!(MALLOCX_ALIGN(1 << la), jemalloc_sys_mallocx_align(1 << la));
+        }
+    }
+}
+
+#[test]
+fn zero() {
+    unsafe { assert_eq!(MALLOCX_ZERO, jemalloc_sys_mallocx_zero()) }
+}
+
+#[test]
+fn tcache() {
+    for tc in 0..1024 {
+        unsafe { assert_eq!(MALLOCX_TCACHE(tc), jemalloc_sys_mallocx_tcache(tc)) }
+    }
+    unsafe { assert_eq!(MALLOCX_TCACHE_NONE(), jemalloc_sys_mallocx_tcache_none()) }
+}
+
+#[test]
+fn arena() {
+    for a in 0..MALLCTL_ARENAS_ALL {
+        unsafe { assert_eq!(MALLOCX_ARENA(a), jemalloc_sys_mallocx_arena(a)) }
+    }
+}
@@ -0,0 +1,21 @@
+#![cfg(all(feature = "shared_library", unix))]
+
+extern crate jemalloc_sys;
+extern crate libc;
+
+use std::ffi::CStr;
+
+#[test]
+fn malloc_is_in_shared_library() {
+    let malloc: unsafe extern "C" fn(usize) -> *mut libc::c_void = jemalloc_sys::malloc;
+    unsafe {
+        let mut info: libc::Dl_info = std::mem::zeroed();
+        assert_ne!(libc::dladdr(malloc as *const libc::c_void, &mut info), 0);
+        let file = CStr::from_ptr(info.dli_fname).to_string_lossy();
+        assert!(
+            file.contains("libjemalloc"),
+            "`malloc` is defined in {}",
+            file
+        );
+    }
+}
@@ -0,0 +1,12 @@
+//! Checks the bindings against the `jemalloc/jemalloc.h` header, see the
+//! `verify_bindings` feature.
+#![allow(bad_style, improper_ctypes, dead_code, unused_imports)]
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::all))]
+
+extern crate jemalloc_sys;
+extern crate libc;
+
+use jemalloc_sys::*;
+use libc::{c_char, c_int, c_void, size_t};
+
+include!(concat!(env!("OUT_DIR"), "/verify_bindings.rs"));
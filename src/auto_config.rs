@@ -0,0 +1,563 @@
+//! Configuration of `jemalloc` from the cgroup limits of the process.
+//!
+//! `jemalloc` sizes itself from the CPUs of the host, e.g., it uses 4 arenas
+//! per CPU, which wastes memory in containers limited to a few CPUs. With the
+//! `auto_config` feature, a constructor running before `main` reads the CPU
+//! quota, the cpuset, and the memory limit of the cgroup of the process and
+//! initializes `jemalloc` with the options derived from them, see
+//! [`Config::new`].
+//!
+//! The options are passed through the `MALLOC_CONF` environment variable of
+//! `jemalloc`, e.g., `_RJEM_MALLOC_CONF`, which is restored once `jemalloc`
+//! is initialized. They take precedence over the `malloc_conf` symbol, while
+//! the options already in the environment variable take precedence over them.
+//!
+//! The options only apply if the constructor runs before `jemalloc` is
+//! initialized. This holds when `jemalloc` is linked statically, the default,
+//! but not when it is a shared library, i.e., with the `shared_library`
+//! feature of `jemalloc-sys` or a system `jemalloc` found with `pkg-config`,
+//! since the constructor of the shared library initializes it first. It also
+//! fails if the constructor of another library allocates from `jemalloc`
+//! before. [`constructor_result`] reports whether the options were applied.
+//!
+//! The cgroup of the process is read from `/proc/self/cgroup` and its files
+//! from the hierarchies mounted under `/sys/fs/cgroup`, see [`Paths`]. If the
+//! `JEMALLOCATOR_CGROUP_ROOT` environment variable is set, the files are read
+//! from the directory it names instead. Both cgroup v2 (`cpu.max`,
+//! `cpuset.cpus.effective`, and `memory.max`) and cgroup v1
+//! (`cpu.cfs_quota_us`, `cpuset.effective_cpus`, and `memory.limit_in_bytes`
+//! in the `cpu`, `cpuset`, and `memory` hierarchies) are supported.
+
+use core::{cmp, fmt, ptr, str};
+
+use libc::{self, c_char};
+
+use ffi;
+
+/// Directories the cgroup files are read from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Paths<'a> {
+    /// Mount point of the cgroup hierarchies: that of cgroup v2, and the
+    /// parent directory of those of cgroup v1, e.g., `<cgroup>/memory`.
+    ///
+    /// Defaults to `/sys/fs/cgroup`.
+    pub cgroup: &'a str,
+
+    /// File listing the cgroups of the process, in the format of
+    /// `/proc/self/cgroup`.
+    ///
+    /// The files are read from the cgroups of the process, e.g.,
+    /// `<cgroup>/system.slice/app.service/memory.max`, or from the root of
+    /// their hierarchy if they do not exist there, e.g., because a container
+    /// mounts its cgroup as the root. If `None`, the files are read from
+    /// `cgroup` directly.
+    ///
+    /// Defaults to `/proc/self/cgroup`.
+    pub proc_cgroup: Option<&'a str>,
+}
+
+impl<'a> Default for Paths<'a> {
+    fn default() -> Self {
+        Self {
+            cgroup: "/sys/fs/cgroup",
+            proc_cgroup: Some("/proc/self/cgroup"),
+        }
+    }
+}
+
+/// The limits of the cgroup of the process.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// CPU quota in CPUs, rounded up.
+    pub cpu_quota: Option<u32>,
+    /// Number of CPUs of the cpuset.
+    pub cpuset: Option<u32>,
+    /// Memory limit in bytes.
+    pub memory: Option<u64>,
+}
+
+impl Limits {
+    /// Reads the limits from the cgroup files in `paths`.
+    ///
+    /// Missing files and unlimited values are `None`.
+    pub fn read(paths: &Paths) -> Self {
+        // `/proc/self/cgroup` lists one line per hierarchy:
+        let mut list = [0; MAX_CONF_LEN + 1];
+        let cgroups = match paths.proc_cgroup.and_then(|p| path(format_args!("{}", p))) {
+            Some(p) => read_file(&p, &mut list).unwrap_or(""),
+            None => "",
+        };
+        let cgroup = Cgroup {
+            mount: paths.cgroup,
+            cgroups,
+        };
+        let mut buf = [0; 256];
+        let cpu_quota = match cgroup.read(None, "cpu.max", &mut buf) {
+            Some(s) => parse_cpu_max(s),
+            None => read_cfs_quota(&cgroup),
+        };
+        let cpuset = match cgroup.read(None, "cpuset.cpus.effective", &mut buf) {
+            Some(s) => parse_cpuset(s),
+            None => cgroup
+                .read(Some("cpuset"), "cpuset.effective_cpus", &mut buf)
+                .and_then(parse_cpuset),
+        };
+        let memory = match cgroup.read(None, "memory.max", &mut buf) {
+            Some(s) => parse_bytes(s),
+            None => cgroup
+                .read(Some("memory"), "memory.limit_in_bytes", &mut buf)
+                .and_then(parse_bytes),
+        };
+        Self {
+            cpu_quota,
+            cpuset,
+            memory,
+        }
+    }
+}
+
+/// Options of `jemalloc` derived from the [`Limits`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Number of arenas, see `opt.narenas`.
+    pub narenas: u32,
+    /// Maximum number of background threads, see
+    /// `opt.max_background_threads`.
+    pub max_background_threads: u32,
+    /// Decay time of the dirty pages, see `opt.dirty_decay_ms`.
+    pub dirty_decay_ms: Option<i64>,
+    /// Decay time of the muzzy pages, see `opt.muzzy_decay_ms`.
+    pub muzzy_decay_ms: Option<i64>,
+}
+
+/// Memory limit up to which the unused pages are purged early.
+const SMALL_MEMORY_LIMIT: u64 = 1 << 30;
+
+impl Config {
+    /// Derives the options from `limits` and the number of CPUs of the host.
+    ///
+    /// With `n` available CPUs, the smallest of `host_cpus` and the CPU
+    /// limits, `jemalloc` uses `4 * n` arenas, or 1 if `n` is 1, and up to
+    /// `n` background threads. With a memory limit of at most 1 GiB, the
+    /// dirty pages decay in 1 second and the muzzy pages are purged
+    /// immediately.
+    ///
+    /// Returns `None` if the limits do not change the defaults of `jemalloc`.
+    pub fn new(limits: &Limits, host_cpus: u32) -> Option<Self> {
+        let cpus = [limits.cpu_quota, limits.cpuset]
+            .iter()
+            .filter_map(|&c| c)
+            .fold(host_cpus, cmp::min)
+            .max(1);
+        let small_memory = limits.memory.map_or(false, |m| m <= SMALL_MEMORY_LIMIT);
+        if cpus == host_cpus && !small_memory {
+            return None;
+        }
+        Some(Self {
+            // `jemalloc` uses a single arena on a single CPU:
+            narenas: if cpus == 1 { 1 } else { 4 * cpus },
+            max_background_threads: cpus,
+            dirty_decay_ms: if small_memory { Some(1000) } else { None },
+            muzzy_decay_ms: if small_memory { Some(0) } else { None },
+        })
+    }
+}
+
+/// Formats the options as `malloc_conf` options.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "narenas:{},max_background_threads:{}",
+            self.narenas, self.max_background_threads
+        )?;
+        if let Some(ms) = self.dirty_decay_ms {
+            write!(f, ",dirty_decay_ms:{}", ms)?;
+        }
+        if let Some(ms) = self.muzzy_decay_ms {
+            write!(f, ",muzzy_decay_ms:{}", ms)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why [`configure`] did not apply the options.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The number of CPUs of the host is unknown.
+    HostCpus,
+    /// The options and those already in the `MALLOC_CONF` environment
+    /// variable exceed [`MAX_CONF_LEN`] bytes.
+    ///
+    /// Nothing can be allocated before `jemalloc` is initialized, so the
+    /// options are formatted into a fixed-size buffer.
+    TooLong,
+    /// `jemalloc` was initialized before, so that it ignored the options,
+    /// see the [module documentation](index.html).
+    Initialized,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::HostCpus => "the number of CPUs of the host is unknown",
+            Error::TooLong => "the `jemalloc` options are too long",
+            Error::Initialized => "`jemalloc` was already initialized",
+        })
+    }
+}
+
+/// Maximum length in bytes of the options passed to `jemalloc`, including
+/// those already in the `MALLOC_CONF` environment variable.
+pub const MAX_CONF_LEN: usize = 4095;
+
+/// Initializes `jemalloc` with the options derived from the cgroup limits
+/// in `paths`, and returns them.
+///
+/// Returns `Ok(None)` if the limits do not change the defaults of
+/// `jemalloc`, which is initialized nevertheless. Fails with
+/// [`Error::Initialized`] if `jemalloc` was already initialized, which is
+/// detected from the options it reports.
+///
+/// This is called by the constructor of the `auto_config` feature.
+///
+/// # Safety
+///
+/// No other threads may access the environment while this runs.
+pub unsafe fn configure(paths: &Paths) -> Result<Option<Config>, Error> {
+    let host_cpus = libc::sysconf(libc::_SC_NPROCESSORS_ONLN);
+    if host_cpus <= 0 {
+        return Err(Error::HostCpus);
+    }
+    let config = match Config::new(&Limits::read(paths), host_cpus as u32) {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+
+    let var = concat!(env!("JEMALLOCATOR_MALLOC_CONF_VAR"), "\0");
+    let var = var.as_ptr() as *const c_char;
+    // The options of the environment variable follow ours so that they take
+    // precedence:
+    let previous = libc::getenv(var);
+    let mut conf = Buffer::new();
+    let mut saved = Buffer::new();
+    let _ = fmt::write(&mut conf, format_args!("{}", config));
+    if !previous.is_null() {
+        let previous = ::core::slice::from_raw_parts(previous as *const u8, libc::strlen(previous));
+        conf.push(b",");
+        conf.push(previous);
+        saved.push(previous);
+    }
+    if conf.overflowed || saved.overflowed {
+        return Err(Error::TooLong);
+    }
+    libc::setenv(var, conf.as_ptr(), 1);
+    // Reading any key initializes `jemalloc`:
+    let _ = read_opt::<libc::c_uint>(b"opt.narenas\0");
+    if previous.is_null() {
+        libc::unsetenv(var);
+    } else {
+        libc::setenv(var, saved.as_ptr(), 1);
+    }
+    if applied(&config, &saved.buf[..saved.len]) {
+        Ok(Some(config))
+    } else {
+        Err(Error::Initialized)
+    }
+}
+
+/// Returns `true` if `jemalloc` reports the options of `config`, except for
+/// those overridden by the `MALLOC_CONF` environment variable `env`.
+unsafe fn applied(config: &Config, env: &[u8]) -> bool {
+    let contains = |key: &str| env.windows(key.len()).any(|w| w == key.as_bytes());
+    let check = |key: &str, name: &[u8], value: Option<i64>| match value {
+        // `size_t` and `ssize_t` options have the size of `isize`:
+        Some(value) => contains(key) || read_opt::<isize>(name) == Some(value as isize),
+        None => true,
+    };
+    let narenas = read_opt::<libc::c_uint>(b"opt.narenas\0");
+    (contains("narenas:") || narenas == Some(config.narenas))
+        && check(
+            "max_background_threads:",
+            b"opt.max_background_threads\0",
+            Some(config.max_background_threads as i64),
+        )
+        && check(
+            "dirty_decay_ms:",
+            b"opt.dirty_decay_ms\0",
+            config.dirty_decay_ms,
+        )
+        && check(
+            "muzzy_decay_ms:",
+            b"opt.muzzy_decay_ms\0",
+            config.muzzy_decay_ms,
+        )
+}
+
+/// Reads the option `name` of `jemalloc`.
+unsafe fn read_opt<T: Copy + Default>(name: &[u8]) -> Option<T> {
+    let mut value = T::default();
+    let mut len = ::core::mem::size_of::<T>();
+    let ret = ffi::mallctl(
+        name.as_ptr() as *const _,
+        &mut value as *mut _ as *mut _,
+        &mut len,
+        ptr::null_mut(),
+        0,
+    );
+    if ret == 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// The result of [`configure`] in the constructor.
+static mut RESULT: Option<Result<Option<Config>, Error>> = None;
+
+/// Returns the result of [`configure`] in the constructor of the
+/// `auto_config` feature, or `None` if the constructor did not run.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate jemallocator;
+///
+/// use jemallocator::auto_config;
+///
+/// #[global_allocator]
+/// static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+///
+/// fn main() {
+///     match auto_config::constructor_result() {
+///         Some(Ok(Some(config))) => println!("configured: {}", config),
+///         Some(Ok(None)) => println!("no cgroup limits"),
+///         Some(Err(e)) => eprintln!("not configured: {}", e),
+///         None => eprintln!("the constructor did not run"),
+///     }
+/// }
+/// ```
+pub fn constructor_result() -> Option<Result<Option<Config>, Error>> {
+    // This is safe because `RESULT` is only written by the constructor,
+    // before `main`:
+    unsafe { RESULT }
+}
+
+#[used]
+#[link_section = ".init_array"]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    unsafe {
+        // Keep the warnings written while `jemalloc` initializes:
+        ::message::install();
+        let root = libc::getenv(b"JEMALLOCATOR_CGROUP_ROOT\0".as_ptr() as *const _);
+        let paths = match cstr(root) {
+            Some(cgroup) => Paths {
+                cgroup,
+                proc_cgroup: None,
+            },
+            None => Paths::default(),
+        };
+        RESULT = Some(configure(&paths));
+    }
+}
+
+/// A null-terminated string in a fixed-size buffer.
+struct Buffer {
+    buf: [u8; MAX_CONF_LEN + 1],
+    len: usize,
+    overflowed: bool,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Self {
+            buf: [0; MAX_CONF_LEN + 1],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Appends `bytes`, or sets `overflowed` if they do not fit.
+    fn push(&mut self, bytes: &[u8]) {
+        // Keep the terminating `\0`:
+        if self.overflowed || self.len + bytes.len() >= self.buf.len() {
+            self.overflowed = true;
+            return;
+        }
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    fn as_ptr(&self) -> *const c_char {
+        self.buf.as_ptr() as *const c_char
+    }
+}
+
+impl fmt::Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        if self.overflowed {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the null-terminated string `s`, or `None` if it is null or not
+/// UTF-8.
+unsafe fn cstr<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    let len = libc::strlen(s);
+    str::from_utf8(::core::slice::from_raw_parts(s as *const u8, len)).ok()
+}
+
+/// The cgroups of the process in the hierarchies mounted under `mount`.
+struct Cgroup<'a> {
+    mount: &'a str,
+    /// The contents of `/proc/self/cgroup`.
+    cgroups: &'a str,
+}
+
+impl<'a> Cgroup<'a> {
+    /// Reads the file `name` of the cgroup of the process into `buf`,
+    /// returning its trimmed contents.
+    ///
+    /// The file is read from the cgroup v2 hierarchy for `None`, and from the
+    /// cgroup v1 hierarchy of `controller` otherwise. If the cgroup of the
+    /// process has no such file, that of the root is read.
+    fn read<'b>(&self, controller: Option<&str>, name: &str, buf: &'b mut [u8]) -> Option<&'b str> {
+        // the cgroup v1 hierarchies are mounted in subdirectories:
+        let (sep, hierarchy) = match controller {
+            Some(controller) => ("/", controller),
+            None => ("", ""),
+        };
+        let cgroup = cgroup_path(self.cgroups, controller);
+        if !cgroup.is_empty() {
+            let own = path(format_args!(
+                "{}{}{}/{}/{}",
+                self.mount, sep, hierarchy, cgroup, name
+            ))?;
+            if unsafe { libc::access(own.as_ptr(), libc::F_OK) } == 0 {
+                return read_file(&own, buf);
+            }
+        }
+        let root = path(format_args!("{}{}{}/{}", self.mount, sep, hierarchy, name))?;
+        read_file(&root, buf)
+    }
+}
+
+/// Returns the path of the cgroup of the process relative to the root of its
+/// hierarchy, given the contents `cgroups` of `/proc/self/cgroup`: that of
+/// cgroup v2 for `None`, and that of the cgroup v1 hierarchy of `controller`
+/// otherwise.
+///
+/// Returns the root, i.e., an empty path, if the cgroup is not listed.
+fn cgroup_path<'a>(cgroups: &'a str, controller: Option<&str>) -> &'a str {
+    cgroups
+        .lines()
+        .filter_map(|line| {
+            // `<id>:<controllers>:<path>`, with no controllers for cgroup v2:
+            let mut fields = line.splitn(3, ':');
+            let _id = fields.next()?;
+            let controllers = fields.next()?;
+            let path = fields.next()?;
+            let listed = match controller {
+                None => controllers.is_empty(),
+                Some(c) => controllers.split(',').any(|name| name == c),
+            };
+            if listed {
+                Some(path.trim_matches('/'))
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap_or("")
+}
+
+/// Formats a null-terminated path, or returns `None` if it is too long.
+fn path(args: fmt::Arguments) -> Option<Buffer> {
+    let mut path = Buffer::new();
+    fmt::write(&mut path, args).ok()?;
+    Some(path)
+}
+
+/// Reads the file at `path` into `buf`, returning its trimmed contents.
+fn read_file<'b>(path: &Buffer, buf: &'b mut [u8]) -> Option<&'b str> {
+    let n = unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return None;
+        }
+        let n = libc::read(fd, buf.as_mut_ptr() as *mut _, buf.len());
+        libc::close(fd);
+        n
+    };
+    if n < 0 {
+        return None;
+    }
+    str::from_utf8(&buf[..n as usize]).ok().map(str::trim)
+}
+
+/// Parses the cgroup v2 `cpu.max`, e.g., `150000 100000` or `max 100000`.
+fn parse_cpu_max(s: &str) -> Option<u32> {
+    let mut fields = s.split_whitespace();
+    let quota = fields.next()?.parse().ok()?;
+    let period = fields.next()?.parse().ok()?;
+    cpus(quota, period)
+}
+
+/// Reads the cgroup v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us`.
+fn read_cfs_quota(cgroup: &Cgroup) -> Option<u32> {
+    let mut buf = [0; 256];
+    // `-1` means no quota and fails to parse:
+    let quota = cgroup
+        .read(Some("cpu"), "cpu.cfs_quota_us", &mut buf)?
+        .parse()
+        .ok()?;
+    let period = cgroup
+        .read(Some("cpu"), "cpu.cfs_period_us", &mut buf)?
+        .parse()
+        .ok()?;
+    cpus(quota, period)
+}
+
+/// Returns the CPUs of a quota of `quota` microseconds per `period`,
+/// rounded up.
+fn cpus(quota: u64, period: u64) -> Option<u32> {
+    if quota == 0 || period == 0 {
+        return None;
+    }
+    Some(((quota + period - 1) / period) as u32)
+}
+
+/// Counts the CPUs of a cpuset list, e.g., `0-3,8`.
+fn parse_cpuset(s: &str) -> Option<u32> {
+    let mut count = 0;
+    for range in s.split(',').filter(|r| !r.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let first: u32 = bounds.next()?.parse().ok()?;
+        let last: u32 = match bounds.next() {
+            Some(last) => last.parse().ok()?,
+            None => first,
+        };
+        count += last.checked_sub(first)? + 1;
+    }
+    if count == 0 {
+        None
+    } else {
+        Some(count)
+    }
+}
+
+/// Parses a memory limit, returning `None` for no limit.
+fn parse_bytes(s: &str) -> Option<u64> {
+    // cgroup v2 reports no limit as `max`, and cgroup v1 as the largest
+    // page-aligned `i64`:
+    s.parse().ok().filter(|&v| v < 1 << 62)
+}
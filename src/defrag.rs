@@ -0,0 +1,188 @@
+//! Defragmentation of long-lived allocations.
+//!
+//! Requires `jemalloc` 5.2. Freeing most of the regions of a slab does not
+//! return it to `jemalloc` while a single region is in use, so long-lived
+//! caches with a high turnover end up spread over many sparsely used slabs.
+//! Similar to the active defragmentation of Redis, [`defrag_box`] and
+//! [`defrag_vec`] move the buffer of an allocation in such a slab to a fresh
+//! allocation, packing the surviving data into fewer slabs, see
+//! [`Jemalloc::should_realloc_for_defrag`].
+//!
+//! The fresh allocations and the deallocations bypass the thread cache, whose
+//! regions may belong to sparse slabs as well. The fresh allocation is served
+//! from the slab the bin allocates from next, which `jemalloc` picks by
+//! address rather than by utilization, so a move is not guaranteed to reduce
+//! fragmentation; it does on average when the slabs below the average
+//! utilization of their bin are moved.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate jemallocator;
+//!
+//! use jemallocator::{defrag, Jemalloc};
+//!
+//! #[global_allocator]
+//! static ALLOC: Jemalloc = Jemalloc;
+//!
+//! fn main() {
+//!     let mut cache: Vec<Vec<u8>> = (0..1000).map(|_| vec![0; 64]).collect();
+//!     // only every 16th entry survives:
+//!     let mut i = 0;
+//!     cache.retain(|_| { i += 1; i % 16 == 0 });
+//!     for entry in &mut cache {
+//!         let buf = std::mem::replace(entry, Vec::new());
+//!         // This is safe because `Jemalloc` is the global allocator:
+//!         *entry = unsafe { defrag::defrag_vec(buf) };
+//!     }
+//! }
+//! ```
+//!
+//! [`defrag_box`]: fn.defrag_box.html
+//! [`defrag_vec`]: fn.defrag_vec.html
+//! [`Jemalloc::should_realloc_for_defrag`]: ../struct.Jemalloc.html#method.should_realloc_for_defrag
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::{mem, ptr};
+
+use libc::c_void;
+
+use {ffi, layout_to_flags, Jemalloc};
+
+/// Utilization of a slab and of its bin, see
+/// `experimental.utilization.query`.
+#[repr(C)]
+struct Utilization {
+    slabcur: *const c_void,
+    nfree: usize,
+    nregs: usize,
+    size: usize,
+    bin_nfree: usize,
+    bin_nregs: usize,
+}
+
+/// Returns the utilization of the slab of `ptr` and of its bin.
+///
+/// The query of `jemalloc` 5.2 crashes if the bin has no current slab, so
+/// with 5.2 only the slab is queried, see
+/// `experimental.utilization.batch_query`, as if `jemalloc` was built
+/// without statistics.
+unsafe fn utilization(ptr: *const c_void) -> Option<Utilization> {
+    let mut u: Utilization = mem::zeroed();
+    #[cfg(jemalloc_5_3)]
+    let (name, out, mut len) = (
+        &b"experimental.utilization.query\0"[..],
+        &mut u as *mut Utilization as *mut c_void,
+        mem::size_of::<Utilization>(),
+    );
+    // `nfree`, `nregs`, and `size` have the layout of a batch result:
+    #[cfg(not(jemalloc_5_3))]
+    let (name, out, mut len) = (
+        &b"experimental.utilization.batch_query\0"[..],
+        &mut u.nfree as *mut usize as *mut c_void,
+        3 * mem::size_of::<usize>(),
+    );
+    let mut ptr = ptr;
+    let ret = ffi::mallctl(
+        name.as_ptr() as *const _,
+        out,
+        &mut len,
+        &mut ptr as *mut _ as *mut _,
+        mem::size_of::<*const c_void>(),
+    );
+    if ret == 0 {
+        Some(u)
+    } else {
+        None
+    }
+}
+
+impl Jemalloc {
+    /// Returns `true` if the allocation `ptr` should be moved to a fresh
+    /// allocation to reduce fragmentation.
+    ///
+    /// Like the active defragmentation of Redis, this is the case if the
+    /// utilization of its slab is at most the average utilization of the
+    /// slabs of its bin, see `experimental.utilization.query`. Without
+    /// statistics, see the `stats` feature, or with `jemalloc` 5.2, the
+    /// average is taken to be one half. Allocations in the slab the bin
+    /// allocates from next, which is only known with `jemalloc` 5.3, and
+    /// allocations of large size classes, are never moved.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Jemalloc` and not been freed yet.
+    pub unsafe fn should_realloc_for_defrag(&self, ptr: *const u8) -> bool {
+        let u = match utilization(ptr as *const c_void) {
+            Some(u) => u,
+            None => return false,
+        };
+        if u.nregs <= 1 || u.nfree == 0 {
+            return false;
+        }
+        let (slabcur, ptr) = (u.slabcur as usize, ptr as usize);
+        if !u.slabcur.is_null() && slabcur <= ptr && ptr < slabcur + u.size {
+            return false;
+        }
+        let used = u.nregs - u.nfree;
+        if u.bin_nregs == 0 {
+            used * 2 <= u.nregs
+        } else {
+            // used / nregs <= (bin_nregs - bin_nfree) / bin_nregs:
+            used * u.bin_nregs <= (u.bin_nregs - u.bin_nfree) * u.nregs
+        }
+    }
+}
+
+/// Moves `size` bytes at `ptr` to a fresh allocation if its slab is sparsely
+/// used, and returns the fresh allocation or `ptr`.
+///
+/// The move may be a no-op for the fragmentation if the fresh allocation
+/// lands in another sparse slab.
+unsafe fn defrag(ptr: *mut u8, align: usize, size: usize) -> *mut u8 {
+    if size == 0 || !Jemalloc.should_realloc_for_defrag(ptr) {
+        return ptr;
+    }
+    let flags = layout_to_flags(align, size) | ffi::MALLOCX_TCACHE_NONE();
+    let new_ptr = ffi::mallocx(size, flags) as *mut u8;
+    if new_ptr.is_null() {
+        return ptr;
+    }
+    ptr::copy_nonoverlapping(ptr, new_ptr, size);
+    ffi::sdallocx(ptr as *mut c_void, size, flags);
+    new_ptr
+}
+
+/// Moves the value of `b` to a fresh allocation if its slab is sparsely
+/// used.
+///
+/// # Safety
+///
+/// `b` must have been allocated by `Jemalloc`, e.g., by being the global
+/// allocator.
+pub unsafe fn defrag_box<T>(b: Box<T>) -> Box<T> {
+    let ptr = Box::into_raw(b) as *mut u8;
+    let ptr = defrag(ptr, mem::align_of::<T>(), mem::size_of::<T>());
+    Box::from_raw(ptr as *mut T)
+}
+
+/// Moves the buffer of `v` to a fresh allocation if its slab is sparsely
+/// used.
+///
+/// The length and capacity of `v` are preserved.
+///
+/// # Safety
+///
+/// `v` must have been allocated by `Jemalloc`, e.g., by being the global
+/// allocator.
+pub unsafe fn defrag_vec<T>(v: Vec<T>) -> Vec<T> {
+    let (len, cap) = (v.len(), v.capacity());
+    let mut v = mem::ManuallyDrop::new(v);
+    let ptr = defrag(
+        v.as_mut_ptr() as *mut u8,
+        mem::align_of::<T>(),
+        cap * mem::size_of::<T>(),
+    );
+    Vec::from_raw_parts(ptr as *mut T, len, cap)
+}
@@ -0,0 +1,206 @@
+//! Forking processes that use `jemalloc`.
+//!
+//! `jemalloc` registers `pthread_atfork` handlers that acquire all its locks
+//! before `fork` and release them in both processes afterwards, so that the
+//! child can keep allocating. Its background threads are not duplicated by
+//! `fork` though: `jemalloc` disables them in the child, which then never
+//! purges its unused pages asynchronously, and a background thread in the
+//! middle of a purge while the parent forks delays the fork until it
+//! releases its locks.
+//!
+//! [`prepare`] stops the background threads and flushes the thread cache of
+//! the calling thread before `fork`, and the returned [`Prepared`] restarts
+//! the background threads in the parent or in the child afterwards. [`fork`]
+//! does all of this around `libc::fork`.
+//!
+//! When `Jemalloc` forwards to the system allocator under a sanitizer,
+//! [`prepare`] does nothing and [`fork`] is `libc::fork`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! extern crate jemallocator;
+//! extern crate libc;
+//!
+//! use jemallocator::{fork, Jemalloc};
+//!
+//! #[global_allocator]
+//! static ALLOC: Jemalloc = Jemalloc;
+//!
+//! fn main() {
+//!     match unsafe { fork::fork() } {
+//!         -1 => panic!("fork failed"),
+//!         0 => {
+//!             // The child allocates with its background threads running:
+//!             let v = vec![0_u8; 1 << 20];
+//!             drop(v);
+//!             unsafe { libc::_exit(0) };
+//!         }
+//!         child => {
+//!             let mut status = 0;
+//!             unsafe { libc::waitpid(child, &mut status, 0) };
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`prepare`]: fn.prepare.html
+//! [`Prepared`]: struct.Prepared.html
+//! [`fork`]: fn.fork.html
+
+#[cfg(not(sanitizer_fallback))]
+use core::{mem, ptr};
+
+use libc;
+#[cfg(not(sanitizer_fallback))]
+use libc::c_char;
+
+#[cfg(not(sanitizer_fallback))]
+use ffi;
+
+#[cfg(not(sanitizer_fallback))]
+const BACKGROUND_THREAD: &[u8] = b"background_thread\0";
+
+/// The state of `jemalloc` saved by [`prepare`] before `fork`.
+///
+/// Call [`Prepared::parent`] in the parent and [`Prepared::child`] in the
+/// child once `fork` returns.
+///
+/// [`prepare`]: fn.prepare.html
+/// [`Prepared::parent`]: struct.Prepared.html#method.parent
+/// [`Prepared::child`]: struct.Prepared.html#method.child
+#[must_use]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Prepared {
+    background_thread: bool,
+}
+
+impl Prepared {
+    /// Returns `true` if the background threads were enabled, and have been
+    /// stopped, before `fork`.
+    pub fn background_thread(&self) -> bool {
+        self.background_thread
+    }
+
+    /// Restores the state of `jemalloc` in the parent after `fork`.
+    ///
+    /// This restarts the background threads stopped by [`prepare`].
+    ///
+    /// [`prepare`]: fn.prepare.html
+    pub fn parent(self) {
+        self.restore()
+    }
+
+    /// Reinitializes the state of `jemalloc` in the child after `fork`.
+    ///
+    /// This restarts the background threads stopped by [`prepare`], which
+    /// the child does not inherit.
+    ///
+    /// [`prepare`]: fn.prepare.html
+    pub fn child(self) {
+        self.restore()
+    }
+
+    fn restore(self) {
+        #[cfg(not(sanitizer_fallback))]
+        {
+            if self.background_thread {
+                unsafe { write_background_thread(true) };
+            }
+        }
+    }
+}
+
+/// Prepares `jemalloc` for `fork`.
+///
+/// This stops the background threads, if they are enabled, and flushes the
+/// thread cache of the calling thread, see `background_thread` and
+/// `thread.tcache.flush`.
+///
+/// # Safety
+///
+/// No other threads may enable the background threads until the returned
+/// [`Prepared`] is used.
+///
+/// [`Prepared`]: struct.Prepared.html
+pub unsafe fn prepare() -> Prepared {
+    #[cfg(not(sanitizer_fallback))]
+    {
+        let background_thread = read_background_thread() && write_background_thread(false);
+        ffi::mallctl(
+            b"thread.tcache.flush\0".as_ptr() as *const c_char,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+        );
+        Prepared { background_thread }
+    }
+    #[cfg(sanitizer_fallback)]
+    {
+        Prepared {
+            background_thread: false,
+        }
+    }
+}
+
+/// Forks the process with `libc::fork` between [`prepare`] and
+/// [`Prepared::parent`] or [`Prepared::child`].
+///
+/// Returns the result of `libc::fork`: the process ID of the child in the
+/// parent, `0` in the child, and `-1` on failure.
+///
+/// # Safety
+///
+/// Same as for `libc::fork`: in a multi-threaded process, the child may only
+/// call async-signal-safe functions, with the exception of the allocation
+/// functions of `jemalloc`. See also [`prepare`].
+///
+/// [`prepare`]: fn.prepare.html
+/// [`Prepared::parent`]: struct.Prepared.html#method.parent
+/// [`Prepared::child`]: struct.Prepared.html#method.child
+pub unsafe fn fork() -> libc::pid_t {
+    #[cfg(not(sanitizer_fallback))]
+    {
+        let prepared = prepare();
+        let pid = libc::fork();
+        if pid == 0 {
+            prepared.child();
+        } else {
+            prepared.parent();
+        }
+        pid
+    }
+    #[cfg(sanitizer_fallback)]
+    {
+        libc::fork()
+    }
+}
+
+/// Reads `background_thread`, returning `false` if it is not supported.
+#[cfg(not(sanitizer_fallback))]
+unsafe fn read_background_thread() -> bool {
+    let mut enabled = false;
+    let mut len = mem::size_of::<bool>();
+    let ret = ffi::mallctl(
+        BACKGROUND_THREAD.as_ptr() as *const c_char,
+        &mut enabled as *mut _ as *mut _,
+        &mut len,
+        ptr::null_mut(),
+        0,
+    );
+    ret == 0 && enabled
+}
+
+/// Writes `background_thread`, returning `true` on success.
+#[cfg(not(sanitizer_fallback))]
+unsafe fn write_background_thread(mut enabled: bool) -> bool {
+    let ret = ffi::mallctl(
+        BACKGROUND_THREAD.as_ptr() as *const c_char,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        &mut enabled as *mut _ as *mut _,
+        mem::size_of::<bool>(),
+    );
+    ret == 0
+}
@@ -19,8 +19,13 @@
 #![deny(missing_docs, intra_doc_link_resolution_failure)]
 #![no_std]
 
+#[cfg(all(jemalloc_5_2, not(sanitizer_fallback)))]
+extern crate alloc;
 extern crate jemalloc_sys;
 extern crate libc;
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
 
 #[cfg(feature = "alloc_trait")]
 use core::alloc::{Alloc, AllocErr, CannotReallocInPlace, Excess};
@@ -92,9 +97,27 @@ macro_rules! assume {
 ///
 /// When the `alloc_trait` feature of this crate is enabled, it also implements the `Alloc` trait,
 /// allowing usage in collections.
+///
+/// When the crate is built with a sanitizer intercepting `malloc`, e.g., `-Zsanitizer=address`,
+/// `Jemalloc` forwards to the system allocator by default so that the sanitizer can check the
+/// allocations; see `JEMALLOC_SYS_SANITIZER_MODE` in the `jemalloc-sys` README.
 #[derive(Copy, Clone, Default, Debug)]
 pub struct Jemalloc;
 
+#[cfg(sanitizer_fallback)]
+mod system;
+
+#[cfg(all(feature = "auto_config", target_os = "linux", not(sanitizer_fallback)))]
+pub mod auto_config;
+#[cfg(all(jemalloc_5_2, not(sanitizer_fallback)))]
+pub mod defrag;
+#[cfg(unix)]
+pub mod fork;
+pub mod message;
+mod ptr_info;
+pub use ptr_info::PtrInfo;
+
+#[cfg(not(sanitizer_fallback))]
 unsafe impl GlobalAlloc for Jemalloc {
     #[inline]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
@@ -169,6 +192,7 @@ unsafe impl Alloc for Jemalloc {
         NonNull::new(GlobalAlloc::realloc(self, ptr.as_ptr(), layout, new_size)).ok_or(AllocErr)
     }
 
+    #[cfg(not(sanitizer_fallback))]
     #[inline]
     unsafe fn alloc_excess(&mut self, layout: Layout) -> Result<Excess, AllocErr> {
         let flags = layout_to_flags(layout.align(), layout.size());
@@ -181,6 +205,7 @@ unsafe impl Alloc for Jemalloc {
         }
     }
 
+    #[cfg(not(sanitizer_fallback))]
     #[inline]
     unsafe fn realloc_excess(
         &mut self,
@@ -198,6 +223,7 @@ unsafe impl Alloc for Jemalloc {
         }
     }
 
+    #[cfg(not(sanitizer_fallback))]
     #[inline]
     fn usable_size(&self, layout: &Layout) -> (usize, usize) {
         let flags = layout_to_flags(layout.align(), layout.size());
@@ -207,6 +233,7 @@ unsafe impl Alloc for Jemalloc {
         }
     }
 
+    #[cfg(not(sanitizer_fallback))]
     #[inline]
     unsafe fn grow_in_place(
         &mut self,
@@ -227,6 +254,7 @@ unsafe impl Alloc for Jemalloc {
         }
     }
 
+    #[cfg(not(sanitizer_fallback))]
     #[inline]
     unsafe fn shrink_in_place(
         &mut self,
@@ -282,7 +310,14 @@ unsafe impl Alloc for Jemalloc {
 ///
 /// `ptr` must have been allocated by `Jemalloc` and must not have been freed yet.
 pub unsafe fn usable_size<T>(ptr: *const T) -> usize {
-    ffi::malloc_usable_size(ptr as *const c_void)
+    #[cfg(not(sanitizer_fallback))]
+    {
+        ffi::malloc_usable_size(ptr as *const c_void)
+    }
+    #[cfg(sanitizer_fallback)]
+    {
+        system::usable_size(ptr as *mut c_void)
+    }
 }
 
 /// Raw bindings to jemalloc
@@ -0,0 +1,247 @@
+//! Redirecting the messages of `jemalloc`.
+//!
+//! `jemalloc` writes its warnings and errors, e.g., about failed system
+//! calls, and the output of `malloc_stats_print` without a write callback,
+//! e.g., with `opt.stats_print` at exit, through `malloc_message`, which
+//! writes them to standard error by default. [`set_callback`] redirects them
+//! to a function, and [`forward_to_log`] to the `log` crate with the `log`
+//! feature.
+//!
+//! The warnings about invalid `MALLOC_CONF` options are written while
+//! `jemalloc` initializes, which happens before `main`, e.g., in its own
+//! constructor or on the first allocation of the Rust runtime. On Linux, a
+//! constructor installs the hook of this module before, which writes the
+//! messages to standard error until a callback is set and keeps the first
+//! [`PENDING_LEN`] bytes of them: [`set_callback`] passes those on to the
+//! callback first. This requires the statically linked `jemalloc`, since the
+//! constructor of a shared `jemalloc` initializes it before.
+//!
+//! When `Jemalloc` forwards to the system allocator under a sanitizer, the
+//! functions of this module do nothing.
+//!
+//! The messages are passed on without allocating, but `jemalloc` may call
+//! `malloc_message` while holding its locks, so the callback and the logger
+//! must not allocate with `jemalloc` either.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate jemallocator;
+//!
+//! use jemallocator::{message, Jemalloc};
+//!
+//! #[global_allocator]
+//! static ALLOC: Jemalloc = Jemalloc;
+//!
+//! fn discard(_message: &str) {}
+//!
+//! fn main() {
+//!     unsafe { message::set_callback(discard) };
+//!     // ...
+//!     unsafe { message::reset() };
+//! }
+//! ```
+//!
+//! [`set_callback`]: fn.set_callback.html
+//! [`forward_to_log`]: fn.forward_to_log.html
+//! [`PENDING_LEN`]: constant.PENDING_LEN.html
+
+#[cfg(not(sanitizer_fallback))]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(sanitizer_fallback))]
+use core::{cmp, ptr, slice, str};
+
+#[cfg(not(sanitizer_fallback))]
+use libc::{self, c_char, c_void};
+
+#[cfg(not(sanitizer_fallback))]
+use ffi;
+
+/// The number of bytes of the messages written before [`set_callback`] that
+/// are passed on to the callback.
+///
+/// [`set_callback`]: fn.set_callback.html
+pub const PENDING_LEN: usize = 1024;
+
+/// The callback of [`set_callback`], or `0`.
+///
+/// [`set_callback`]: fn.set_callback.html
+#[cfg(not(sanitizer_fallback))]
+static CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the messages are still kept for [`set_callback`], i.e., until
+/// it or [`reset`] is called.
+///
+/// [`set_callback`]: fn.set_callback.html
+/// [`reset`]: fn.reset.html
+#[cfg(not(sanitizer_fallback))]
+static KEEP_PENDING: AtomicBool = AtomicBool::new(true);
+
+/// The messages written before [`set_callback`], of which the first
+/// `PENDING_LEN` bytes are in `PENDING`.
+///
+/// [`set_callback`]: fn.set_callback.html
+#[cfg(not(sanitizer_fallback))]
+static PENDING_USED: AtomicUsize = AtomicUsize::new(0);
+#[cfg(not(sanitizer_fallback))]
+static mut PENDING: [u8; PENDING_LEN] = [0; PENDING_LEN];
+
+/// Redirects the messages of `jemalloc` to `callback`.
+///
+/// `callback` is called with each message as written by `jemalloc`,
+/// including the `<jemalloc>: ` prefix of warnings and errors and the
+/// trailing newlines. A single message may be split across several calls,
+/// e.g., for the output of `malloc_stats_print`. Invalid UTF-8 is truncated.
+///
+/// On the first call, `callback` is first called with the messages written
+/// before, up to [`PENDING_LEN`] bytes, e.g., with the warnings about
+/// invalid `MALLOC_CONF` options.
+///
+/// # Safety
+///
+/// No other threads may call the functions of this module, or write
+/// `malloc_message`, concurrently. `callback` must not allocate with
+/// `jemalloc`.
+///
+/// [`PENDING_LEN`]: constant.PENDING_LEN.html
+pub unsafe fn set_callback(callback: fn(&str)) {
+    #[cfg(not(sanitizer_fallback))]
+    {
+        CALLBACK.store(callback as usize, Ordering::SeqCst);
+        ffi::malloc_message = Some(write_message);
+        if KEEP_PENDING.swap(false, Ordering::SeqCst) {
+            let len = cmp::min(PENDING_USED.load(Ordering::SeqCst), PENDING_LEN);
+            let pending = &*ptr::addr_of!(PENDING);
+            if len > 0 {
+                callback(utf8_prefix(&pending[..len]));
+            }
+        }
+    }
+    #[cfg(sanitizer_fallback)]
+    {
+        let _ = callback;
+    }
+}
+
+/// Redirects the messages of `jemalloc` to the `log` crate.
+///
+/// Warnings and errors are logged at the `Warn` level, without their
+/// `<jemalloc>: ` prefix, and other messages, e.g., statistics, at the
+/// `Info` level. Their target is `jemalloc`, and their trailing newlines
+/// are removed.
+///
+/// # Safety
+///
+/// See [`set_callback`]. The logger must not allocate with `jemalloc`.
+///
+/// [`set_callback`]: fn.set_callback.html
+#[cfg(feature = "log")]
+pub unsafe fn forward_to_log() {
+    #[cfg(not(sanitizer_fallback))]
+    {
+        set_callback(log_message)
+    }
+}
+
+/// Restores the default `malloc_message` of `jemalloc`, which writes the
+/// messages to standard error.
+///
+/// # Safety
+///
+/// See [`set_callback`].
+///
+/// [`set_callback`]: fn.set_callback.html
+pub unsafe fn reset() {
+    #[cfg(not(sanitizer_fallback))]
+    {
+        ffi::malloc_message = None;
+        CALLBACK.store(0, Ordering::SeqCst);
+        KEEP_PENDING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Installs `write_message` before `jemalloc` is initialized, so that the
+/// messages written while it initializes are kept for [`set_callback`].
+///
+/// Also called by the constructor of `auto_config`, which initializes
+/// `jemalloc` and may run first.
+///
+/// [`set_callback`]: fn.set_callback.html
+#[cfg(all(target_os = "linux", not(sanitizer_fallback)))]
+pub(crate) unsafe fn install() {
+    if CALLBACK.load(Ordering::SeqCst) == 0 && KEEP_PENDING.load(Ordering::SeqCst) {
+        ffi::malloc_message = Some(write_message);
+    }
+}
+
+#[cfg(all(target_os = "linux", not(sanitizer_fallback)))]
+#[used]
+#[link_section = ".init_array"]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+#[cfg(all(target_os = "linux", not(sanitizer_fallback)))]
+extern "C" fn constructor() {
+    unsafe { install() }
+}
+
+#[cfg(not(sanitizer_fallback))]
+extern "C" fn write_message(_cbopaque: *mut c_void, s: *const c_char) {
+    if s.is_null() {
+        return;
+    }
+    let bytes = unsafe { slice::from_raw_parts(s as *const u8, libc::strlen(s)) };
+    let callback = CALLBACK.load(Ordering::SeqCst);
+    if callback == 0 {
+        keep_pending(bytes);
+        unsafe {
+            libc::write(
+                libc::STDERR_FILENO,
+                bytes.as_ptr() as *const c_void,
+                bytes.len(),
+            )
+        };
+        return;
+    }
+    let callback: fn(&str) = unsafe { ::core::mem::transmute(callback) };
+    callback(utf8_prefix(bytes))
+}
+
+/// Appends `bytes` to `PENDING` while the messages are kept, as far as they
+/// fit.
+#[cfg(not(sanitizer_fallback))]
+fn keep_pending(bytes: &[u8]) {
+    if !KEEP_PENDING.load(Ordering::SeqCst) {
+        return;
+    }
+    let start = PENDING_USED.fetch_add(bytes.len(), Ordering::SeqCst);
+    if start >= PENDING_LEN {
+        return;
+    }
+    let len = cmp::min(bytes.len(), PENDING_LEN - start);
+    // The ranges reserved by `fetch_add` do not overlap:
+    unsafe {
+        let pending = &mut *ptr::addr_of_mut!(PENDING);
+        pending[start..start + len].copy_from_slice(&bytes[..len]);
+    }
+}
+
+/// Returns the longest UTF-8 prefix of `bytes`.
+#[cfg(not(sanitizer_fallback))]
+fn utf8_prefix(bytes: &[u8]) -> &str {
+    match str::from_utf8(bytes) {
+        Ok(message) => message,
+        Err(e) => unsafe { str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) },
+    }
+}
+
+#[cfg(all(feature = "log", not(sanitizer_fallback)))]
+fn log_message(message: &str) {
+    let (level, message) = match message.strip_prefix("<jemalloc>: ") {
+        Some(message) => (::log::Level::Warn, message),
+        None => (::log::Level::Info, message),
+    };
+    let message = message.trim_end_matches('\n');
+    if !message.is_empty() {
+        log!(target: "jemalloc", level, "{}", message);
+    }
+}
@@ -0,0 +1,169 @@
+//! Introspection of the allocations of `Jemalloc`.
+
+#[cfg(not(sanitizer_fallback))]
+use core::{mem, ptr};
+
+use libc::c_uint;
+#[cfg(not(sanitizer_fallback))]
+use libc::c_void;
+
+#[cfg(not(sanitizer_fallback))]
+use ffi;
+
+/// The arena, usable size, and size class of an allocation.
+///
+/// This is meant for debugging tools, e.g., to check that the data of a
+/// subsystem lives in the arena it binds its threads to.
+///
+/// # Examples
+///
+/// ```
+/// extern crate jemallocator;
+///
+/// use jemallocator::{Jemalloc, PtrInfo};
+///
+/// #[global_allocator]
+/// static ALLOC: Jemalloc = Jemalloc;
+///
+/// fn main() {
+///     let data = Box::new([0_u8; 100]);
+///     let info = unsafe { PtrInfo::of(data.as_ptr()) }.unwrap();
+///     assert_eq!(info.usable_size, 112);
+///     assert!(info.bin.is_some());
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PtrInfo {
+    /// Index of the arena owning the allocation, see `arenas.lookup`.
+    pub arena: c_uint,
+    /// Usable size of the allocation, see `sallocx`.
+    ///
+    /// This is the size of the size class of the allocation.
+    pub usable_size: usize,
+    /// Index of the bin of the size class, see `arenas.bin.<i>.size`.
+    ///
+    /// `None` for large size classes, which are not served from slabs.
+    pub bin: Option<c_uint>,
+}
+
+impl PtrInfo {
+    /// Returns the information about the allocation `ptr`.
+    ///
+    /// Returns `None` if `jemalloc` cannot provide it, e.g., when it forwards
+    /// to the system allocator under a sanitizer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Jemalloc` and not been freed yet.
+    /// Pointers into an allocation are not supported: `sallocx` reads the
+    /// metadata at the address of `ptr`, which is undefined behavior for
+    /// interior pointers.
+    pub unsafe fn of<T>(ptr: *const T) -> Option<Self> {
+        #[cfg(not(sanitizer_fallback))]
+        {
+            let ptr = ptr as *const c_void;
+            let arena = lookup(ptr)?;
+            let usable_size = ffi::sallocx(ptr, 0);
+            let bin = bin(usable_size)?;
+            Some(Self {
+                arena,
+                usable_size,
+                bin,
+            })
+        }
+        #[cfg(sanitizer_fallback)]
+        {
+            let _ = ptr;
+            None
+        }
+    }
+}
+
+/// Returns the index of the arena owning `ptr`.
+#[cfg(not(sanitizer_fallback))]
+unsafe fn lookup(ptr: *const c_void) -> Option<c_uint> {
+    let mut arena: c_uint = 0;
+    let mut len = mem::size_of::<c_uint>();
+    let mut ptr = ptr;
+    let ret = ffi::mallctl(
+        b"arenas.lookup\0".as_ptr() as *const _,
+        &mut arena as *mut _ as *mut _,
+        &mut len,
+        &mut ptr as *mut _ as *mut _,
+        mem::size_of::<*const c_void>(),
+    );
+    if ret == 0 {
+        Some(arena)
+    } else {
+        None
+    }
+}
+
+/// Returns the index of the bin of the size class `size`, or `Some(None)` if
+/// it is a large size class.
+#[cfg(not(sanitizer_fallback))]
+unsafe fn bin(size: usize) -> Option<Option<c_uint>> {
+    let nbins: c_uint = read(b"arenas.nbins\0")?;
+    let mut mib = [0; 4];
+    let mut len = mib.len();
+    if ffi::mallctlnametomib(
+        b"arenas.bin.0.size\0".as_ptr() as *const _,
+        mib.as_mut_ptr(),
+        &mut len,
+    ) != 0
+    {
+        return None;
+    }
+    // The size classes of the bins are increasing:
+    let (mut lo, mut hi) = (0, nbins);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        mib[2] = mid as usize;
+        let bin_size: usize = read_mib(&mib)?;
+        if bin_size == size {
+            return Some(Some(mid));
+        } else if bin_size < size {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    Some(None)
+}
+
+#[cfg(not(sanitizer_fallback))]
+unsafe fn read<T: Copy + Default>(name: &[u8]) -> Option<T> {
+    let mut value = T::default();
+    let mut len = mem::size_of::<T>();
+    let ret = ffi::mallctl(
+        name.as_ptr() as *const _,
+        &mut value as *mut _ as *mut _,
+        &mut len,
+        ptr::null_mut(),
+        0,
+    );
+    if ret == 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(sanitizer_fallback))]
+unsafe fn read_mib<T: Copy + Default>(mib: &[usize]) -> Option<T> {
+    let mut value = T::default();
+    let mut len = mem::size_of::<T>();
+    let ret = ffi::mallctlbymib(
+        mib.as_ptr(),
+        mib.len(),
+        &mut value as *mut _ as *mut _,
+        &mut len,
+        ptr::null_mut(),
+        0,
+    );
+    if ret == 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,82 @@
+//! Forwarding of `Jemalloc` to the system allocator.
+//!
+//! This is used instead of `jemalloc` when the crate is built with a sanitizer
+//! intercepting `malloc`, e.g., `-Zsanitizer=address`, so that the sanitizer
+//! can check the allocations. See `JEMALLOC_SYS_SANITIZER_MODE` in the
+//! `jemalloc-sys` README.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::{cmp, mem, ptr};
+
+use libc::{self, c_void};
+
+use {alignof_max_align_t, Jemalloc};
+
+/// Returns `true` if `malloc` returns allocations of `size` suitably aligned
+/// to `align`, see `layout_to_flags`.
+fn malloc_aligned(align: usize, size: usize) -> bool {
+    align <= alignof_max_align_t && align <= size
+}
+
+unsafe fn aligned_alloc(align: usize, size: usize) -> *mut u8 {
+    if malloc_aligned(align, size) {
+        return libc::malloc(size) as *mut u8;
+    }
+    let mut ptr = ptr::null_mut();
+    // `posix_memalign` requires a multiple of the pointer size:
+    let align = cmp::max(align, mem::size_of::<usize>());
+    if libc::posix_memalign(&mut ptr, align, size) == 0 {
+        ptr as *mut u8
+    } else {
+        ptr::null_mut()
+    }
+}
+
+unsafe impl GlobalAlloc for Jemalloc {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        aligned_alloc(layout.align(), layout.size())
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if malloc_aligned(layout.align(), layout.size()) {
+            return libc::calloc(1, layout.size()) as *mut u8;
+        }
+        let ptr = aligned_alloc(layout.align(), layout.size());
+        if !ptr.is_null() {
+            ptr::write_bytes(ptr, 0, layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        libc::free(ptr as *mut c_void)
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if malloc_aligned(layout.align(), new_size) {
+            return libc::realloc(ptr as *mut c_void, new_size) as *mut u8;
+        }
+        let new_ptr = aligned_alloc(layout.align(), new_size);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, cmp::min(layout.size(), new_size));
+            libc::free(ptr as *mut c_void);
+        }
+        new_ptr
+    }
+}
+
+/// Returns the usable size of an allocation of the system allocator.
+pub unsafe fn usable_size(ptr: *mut c_void) -> usize {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        libc::malloc_size(ptr)
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    {
+        libc::malloc_usable_size(ptr)
+    }
+}
@@ -0,0 +1,190 @@
+#![cfg(all(feature = "auto_config", target_os = "linux"))]
+
+extern crate jemalloc_ctl;
+extern crate jemallocator;
+
+use jemalloc_ctl::raw;
+use jemallocator::auto_config::{self, Config, Limits, Paths};
+use jemallocator::Jemalloc;
+use std::{env, fs, process};
+
+#[global_allocator]
+static A: Jemalloc = Jemalloc;
+
+fn cgroup(name: &str, files: &[(&str, &str)]) -> String {
+    let dir = env::temp_dir().join(format!("jemallocator-{}-{}", name, process::id()));
+    for &(file, contents) in files {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir.to_str().unwrap().to_string()
+}
+
+#[test]
+fn cgroup_v2() {
+    let dir = cgroup(
+        "v2",
+        &[
+            ("cpu.max", "150000 100000\n"),
+            ("cpuset.cpus.effective", "0-3,8\n"),
+            ("memory.max", "536870912\n"),
+        ],
+    );
+    let limits = Limits::read(&Paths {
+        cgroup: &dir,
+        proc_cgroup: None,
+    });
+    assert_eq!(
+        limits,
+        Limits {
+            cpu_quota: Some(2),
+            cpuset: Some(5),
+            memory: Some(512 << 20),
+        }
+    );
+    let config = Config::new(&limits, 64).unwrap();
+    assert_eq!(config.narenas, 8);
+    assert_eq!(config.max_background_threads, 2);
+    assert_eq!(
+        config.to_string(),
+        "narenas:8,max_background_threads:2,dirty_decay_ms:1000,muzzy_decay_ms:0"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cgroup_v1() {
+    let dir = cgroup(
+        "v1",
+        &[
+            ("cpu/cpu.cfs_quota_us", "-1\n"),
+            ("cpu/cpu.cfs_period_us", "100000\n"),
+            ("cpuset/cpuset.effective_cpus", "2\n"),
+            ("memory/memory.limit_in_bytes", "9223372036854771712\n"),
+        ],
+    );
+    let limits = Limits::read(&Paths {
+        cgroup: &dir,
+        proc_cgroup: None,
+    });
+    assert_eq!(
+        limits,
+        Limits {
+            cpu_quota: None,
+            cpuset: Some(1),
+            memory: None,
+        }
+    );
+    let config = Config::new(&limits, 64).unwrap();
+    assert_eq!(config.to_string(), "narenas:1,max_background_threads:1");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unlimited() {
+    let dir = cgroup(
+        "unlimited",
+        &[("cpu.max", "max 100000\n"), ("memory.max", "max\n")],
+    );
+    let limits = Limits::read(&Paths {
+        cgroup: &dir,
+        proc_cgroup: None,
+    });
+    assert_eq!(limits, Limits::default());
+    assert_eq!(Config::new(&limits, 4), None);
+    assert_eq!(
+        Limits::read(&Paths {
+            cgroup: "/nonexistent",
+            proc_cgroup: Some("/nonexistent"),
+        }),
+        Limits::default()
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cgroup_of_the_process() {
+    let dir = cgroup(
+        "process",
+        &[
+            ("system.slice/app.service/cpu.max", "200000 100000\n"),
+            ("memory.max", "536870912\n"),
+            ("cpuset/app/cpuset.effective_cpus", "0-2\n"),
+            ("cgroup", "3:cpuset:/app\n0::/system.slice/app.service\n"),
+        ],
+    );
+    let proc_cgroup = format!("{}/cgroup", dir);
+    let limits = Limits::read(&Paths {
+        cgroup: &dir,
+        proc_cgroup: Some(&proc_cgroup),
+    });
+    // `memory.max` is only found at the root, e.g., if a container mounts its
+    // cgroup as the root:
+    assert_eq!(
+        limits,
+        Limits {
+            cpu_quota: Some(2),
+            cpuset: Some(3),
+            memory: Some(512 << 20),
+        }
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Re-runs the `constructor` test with `JEMALLOCATOR_CGROUP_ROOT` pointing at
+/// a fake cgroup and `MALLOC_CONF` set to `conf`.
+fn run_constructor(dir: &str, conf: Option<&str>, narenas: u32) {
+    let var = env!("JEMALLOCATOR_MALLOC_CONF_VAR");
+    let mut child = process::Command::new(env::current_exe().unwrap());
+    child
+        .args(&["--exact", "constructor", "--test-threads=1"])
+        .env("JEMALLOCATOR_CGROUP_ROOT", dir)
+        .env("JEMALLOCATOR_TEST_NARENAS", narenas.to_string())
+        .env_remove(var);
+    if let Some(conf) = conf {
+        child.env(var, conf);
+    }
+    assert!(child.status().unwrap().success());
+}
+
+#[test]
+fn constructor() {
+    if let Ok(narenas) = env::var("JEMALLOCATOR_TEST_NARENAS") {
+        let config = auto_config::constructor_result().unwrap();
+        if cfg!(feature = "shared_library") {
+            // the constructor of the shared library initializes `jemalloc`
+            // first:
+            assert_eq!(config, Err(auto_config::Error::Initialized));
+            return;
+        }
+        assert_eq!(
+            config.unwrap().unwrap().to_string(),
+            "narenas:1,max_background_threads:1,dirty_decay_ms:1000,muzzy_decay_ms:0"
+        );
+        unsafe {
+            assert_eq!(
+                raw::read::<u32>(b"opt.narenas\0").unwrap().to_string(),
+                narenas
+            );
+            assert_eq!(
+                raw::read::<usize>(b"opt.max_background_threads\0").unwrap(),
+                1
+            );
+            assert_eq!(raw::read::<isize>(b"opt.dirty_decay_ms\0").unwrap(), 1000);
+            assert_eq!(raw::read::<isize>(b"opt.muzzy_decay_ms\0").unwrap(), 0);
+        }
+        return;
+    }
+    let dir = cgroup(
+        "constructor",
+        &[
+            ("cpuset.cpus.effective", "0\n"),
+            ("memory.max", "536870912\n"),
+        ],
+    );
+    run_constructor(&dir, None, 1);
+    // the options of the environment variable take precedence:
+    run_constructor(&dir, Some("narenas:3"), 3);
+    fs::remove_dir_all(dir).unwrap();
+}
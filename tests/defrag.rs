@@ -0,0 +1,70 @@
+#![cfg(jemalloc_5_2)]
+
+extern crate jemalloc_ctl;
+extern crate jemallocator;
+
+use jemalloc_ctl::{arenas, thread};
+use jemallocator::{defrag, Jemalloc};
+
+#[global_allocator]
+static A: Jemalloc = Jemalloc;
+
+#[test]
+fn sparse_slab() {
+    // keep the other tests out of the slabs of this test:
+    let arena = arenas::create::read().unwrap();
+    thread::arena::write(arena).unwrap();
+    thread::tcache_flush::invoke().unwrap();
+
+    let mut boxes: Vec<Option<Box<[u64; 12]>>> =
+        (0..4096).map(|i| Some(Box::new([i; 12]))).collect();
+    // empty the slabs of the first half but for two survivors, and keep the
+    // slabs of the second half full:
+    for (i, b) in boxes.iter_mut().enumerate().take(2048) {
+        if i != 100 && i != 1000 {
+            *b = None;
+        }
+    }
+    thread::tcache_flush::invoke().unwrap();
+
+    // the bin allocates from one of the sparse slabs next, so at least the
+    // survivor in the other one is moved, to the slab the bin allocates from
+    // next, which is only known with `jemalloc` 5.3:
+    let mut moved = 0;
+    for &i in &[100, 1000] {
+        let survivor = boxes[i].take().unwrap();
+        let ptr = &*survivor as *const _ as *const u8;
+        if !unsafe { A.should_realloc_for_defrag(ptr) } {
+            continue;
+        }
+        let survivor = unsafe { defrag::defrag_box(survivor) };
+        assert_eq!(*survivor, [i as u64; 12]);
+        let ptr = &*survivor as *const _ as *const u8;
+        if cfg!(jemalloc_5_3) {
+            assert!(!unsafe { A.should_realloc_for_defrag(ptr) });
+        }
+        moved += 1;
+    }
+    assert!(moved >= 1);
+}
+
+#[test]
+fn full_slab() {
+    let large = vec![7_u8; 1 << 20];
+    assert!(!unsafe { A.should_realloc_for_defrag(large.as_ptr()) });
+    let large = unsafe { defrag::defrag_vec(large) };
+    assert_eq!(large.len(), 1 << 20);
+    assert!(large.iter().all(|&b| b == 7));
+
+    let empty: Vec<u32> = Vec::with_capacity(0);
+    assert_eq!(unsafe { defrag::defrag_vec(empty) }.capacity(), 0);
+}
+
+#[test]
+fn vec_contents() {
+    let mut v = Vec::with_capacity(10);
+    v.extend_from_slice(&[1_u16, 2, 3]);
+    let v = unsafe { defrag::defrag_vec(v) };
+    assert_eq!(v, [1, 2, 3]);
+    assert_eq!(v.capacity(), 10);
+}
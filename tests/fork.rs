@@ -0,0 +1,116 @@
+//! Test allocating in both processes after forking, with and without
+//! background threads.
+#![cfg(target_os = "linux")]
+#![cfg(feature = "background_threads_runtime_support")]
+#![cfg(not(target_env = "musl"))]
+
+extern crate jemalloc_ctl;
+extern crate jemallocator;
+extern crate libc;
+
+use jemalloc_ctl::background_thread;
+#[cfg(not(sanitizer_fallback))]
+use jemalloc_ctl::max_background_threads;
+use jemallocator::{fork, Jemalloc};
+#[cfg(not(sanitizer_fallback))]
+use std::cmp;
+use std::sync::Mutex;
+use std::{panic, thread};
+
+#[global_allocator]
+static A: Jemalloc = Jemalloc;
+
+// The tests toggle the background threads of the whole process:
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Allocates from several threads, returning the sum of the allocations.
+fn allocate() -> usize {
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            thread::spawn(move || {
+                let v: Vec<Vec<u8>> = (0..100).map(|j| vec![1; i * 100 + j]).collect();
+                v.iter()
+                    .map(|b| b.iter().map(|&x| x as usize).sum::<usize>())
+                    .sum::<usize>()
+            })
+        })
+        .collect();
+    threads.into_iter().map(|t| t.join().unwrap()).sum()
+}
+
+/// Forks while other threads allocate, runs `child` in the child, and
+/// returns its exit status.
+fn run_forked<F: FnOnce() -> bool>(child: F) -> i32 {
+    let busy = thread::spawn(allocate);
+    let pid = unsafe { fork::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        let ok = panic::catch_unwind(panic::AssertUnwindSafe(child)).unwrap_or(false);
+        unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+    }
+    let expected = allocate();
+    assert_eq!(busy.join().unwrap(), expected);
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+    assert!(libc::WIFEXITED(status));
+    libc::WEXITSTATUS(status)
+}
+
+#[test]
+fn fork_without_background_threads() {
+    let _lock = LOCK.lock().unwrap();
+    background_thread::write(false).unwrap();
+    let status = run_forked(|| {
+        let expected: usize = (0..4)
+            .map(|i| (0..100).map(|j| i * 100 + j).sum::<usize>())
+            .sum();
+        allocate() == expected && !background_thread::read().unwrap()
+    });
+    assert_eq!(status, 0);
+    assert!(!background_thread::read().unwrap());
+}
+
+#[test]
+#[cfg(not(sanitizer_fallback))]
+fn fork_with_background_threads() {
+    let _lock = LOCK.lock().unwrap();
+    // The maximum cannot exceed its default, i.e., the number of CPUs:
+    let prev = max_background_threads::read().unwrap();
+    max_background_threads::write(cmp::min(2, prev)).unwrap();
+    background_thread::write(true).unwrap();
+    let status = run_forked(|| {
+        // The background threads are restarted in the child:
+        allocate() > 0 && background_thread::read().unwrap()
+    });
+    assert_eq!(status, 0);
+    assert!(background_thread::read().unwrap());
+    background_thread::write(false).unwrap();
+    max_background_threads::write(prev).unwrap();
+}
+
+#[test]
+#[cfg(not(sanitizer_fallback))]
+fn prepare() {
+    let _lock = LOCK.lock().unwrap();
+    background_thread::write(true).unwrap();
+    let prepared = unsafe { fork::prepare() };
+    assert!(prepared.background_thread());
+    assert!(!background_thread::read().unwrap());
+    prepared.parent();
+    assert!(background_thread::read().unwrap());
+
+    background_thread::write(false).unwrap();
+    let prepared = unsafe { fork::prepare() };
+    assert!(!prepared.background_thread());
+    prepared.parent();
+    assert!(!background_thread::read().unwrap());
+}
+
+#[test]
+#[cfg(sanitizer_fallback)]
+fn prepare_without_jemalloc() {
+    let _lock = LOCK.lock().unwrap();
+    let prepared = unsafe { fork::prepare() };
+    assert!(!prepared.background_thread());
+    prepared.parent();
+}
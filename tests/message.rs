@@ -0,0 +1,144 @@
+#![cfg(not(sanitizer_fallback))]
+
+extern crate jemalloc_sys;
+extern crate jemallocator;
+#[cfg(feature = "log")]
+extern crate log;
+
+use jemallocator::{message, Jemalloc};
+use std::sync::Mutex;
+use std::{env, process, ptr};
+
+#[global_allocator]
+static A: Jemalloc = Jemalloc;
+
+// The tests redirect the messages of the whole process:
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// The beginning of the messages received, in a buffer that does not
+/// allocate.
+struct Received {
+    buf: [u8; 256],
+    len: usize,
+}
+
+static RECEIVED: Mutex<Received> = Mutex::new(Received {
+    buf: [0; 256],
+    len: 0,
+});
+
+impl Received {
+    fn push(&mut self, message: &str) {
+        let n = message.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&message.as_bytes()[..n]);
+        self.len += n;
+    }
+
+    fn take() -> String {
+        let mut received = RECEIVED.lock().unwrap();
+        let s = String::from_utf8_lossy(&received.buf[..received.len]).into_owned();
+        received.len = 0;
+        s
+    }
+}
+
+fn receive(message: &str) {
+    RECEIVED.lock().unwrap().push(message)
+}
+
+fn print_stats() {
+    // Without a write callback, `malloc_stats_print` writes through
+    // `malloc_message`:
+    unsafe { jemalloc_sys::malloc_stats_print(None, ptr::null_mut(), ptr::null()) };
+}
+
+#[test]
+fn callback() {
+    let _lock = LOCK.lock().unwrap();
+    Received::take();
+    unsafe { message::set_callback(receive) };
+    print_stats();
+    unsafe { message::reset() };
+    assert!(Received::take().contains("Begin jemalloc statistics"));
+
+    // The default `malloc_message` writes to standard error again:
+    print_stats();
+    assert_eq!(Received::take(), "");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn malloc_conf_warnings() {
+    let var = env!("JEMALLOCATOR_MALLOC_CONF_VAR");
+    if env::var_os("JEMALLOCATOR_TEST_MESSAGE").is_some() {
+        let _lock = LOCK.lock().unwrap();
+        Received::take();
+        unsafe { message::set_callback(receive) };
+        unsafe { message::reset() };
+        let received = Received::take();
+        if cfg!(feature = "shared_library") {
+            // the constructor of the shared library initializes `jemalloc`
+            // first:
+            assert_eq!(received, "");
+        } else {
+            assert!(received.contains("<jemalloc>: Invalid conf pair: invalid_option:1"));
+        }
+        return;
+    }
+    let status = process::Command::new(env::current_exe().unwrap())
+        .args(&["--exact", "malloc_conf_warnings", "--test-threads=1"])
+        .env("JEMALLOCATOR_TEST_MESSAGE", "1")
+        .env(var, "invalid_option:1")
+        .stderr(process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[cfg(feature = "log")]
+mod logger {
+    use super::*;
+    use log::{Level, Log, Metadata, Record};
+    use std::fmt::Write;
+
+    struct Logger;
+
+    impl Log for Logger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "jemalloc" && metadata.level() == Level::Info
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                let _ = write!(Writer, "{}", record.args());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    struct Writer;
+
+    impl Write for Writer {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            receive(s);
+            Ok(())
+        }
+    }
+
+    static LOGGER: Logger = Logger;
+
+    #[test]
+    fn forward_to_log() {
+        let _lock = LOCK.lock().unwrap();
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+        Received::take();
+        unsafe { message::forward_to_log() };
+        print_stats();
+        unsafe { message::reset() };
+        let received = Received::take();
+        assert!(received.contains("Begin jemalloc statistics"));
+        assert!(!received.ends_with('\n'));
+    }
+}
@@ -0,0 +1,48 @@
+extern crate jemalloc_ctl;
+extern crate jemallocator;
+
+use jemalloc_ctl::{arenas, thread};
+use jemallocator::{Jemalloc, PtrInfo};
+
+#[global_allocator]
+static A: Jemalloc = Jemalloc;
+
+#[test]
+fn small() {
+    let a = Box::new([0_u8; 100]);
+    let info = unsafe { PtrInfo::of(a.as_ptr()) }.unwrap();
+    assert_eq!(info.usable_size, unsafe {
+        jemallocator::usable_size(a.as_ptr())
+    });
+    let bin = info.bin.unwrap();
+    assert!(bin < arenas::nbins::read().unwrap());
+    let b = Box::new(0_u8);
+    assert!(unsafe { PtrInfo::of(&*b) }.unwrap().bin.unwrap() < bin);
+}
+
+#[test]
+fn large() {
+    let a = vec![0_u8; 1 << 20];
+    let info = unsafe { PtrInfo::of(a.as_ptr()) }.unwrap();
+    assert!(info.usable_size >= 1 << 20);
+    assert_eq!(info.bin, None);
+}
+
+#[test]
+fn arena() {
+    std::thread::spawn(|| {
+        let arena = arenas::create::read().unwrap();
+        thread::arena::write(arena).unwrap();
+        // the cache still holds memory of the previous arena:
+        thread::tcache_flush::invoke().unwrap();
+        let a = Box::new(7_u64);
+        let info = unsafe { PtrInfo::of(&*a) }.unwrap();
+        assert_eq!(info.arena, arena);
+        assert_eq!(
+            unsafe { arenas::lookup(&*a as *const u64 as *const _) }.unwrap(),
+            arena
+        );
+    })
+    .join()
+    .unwrap();
+}